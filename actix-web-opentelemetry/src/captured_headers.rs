@@ -0,0 +1,90 @@
+//! # Captured Headers
+//!
+//! Record selected headers as span attributes.
+use actix_web::http::header::{self, HeaderMap, HeaderName};
+use opentelemetry::{Key, KeyValue};
+use std::borrow::Cow;
+
+// Headers that are never recorded, as they carry credentials.
+const DENIED: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+/// Request and response headers to record as span attributes.
+///
+/// Headers are recorded as `http.request.header.<name>` and `http.response.header.<name>`
+/// with the lowercase header name, dashes replaced by underscores, and all values of the
+/// header. `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` are never
+/// recorded, even if allowed.
+///
+/// # Examples
+///
+/// ```
+/// use actix_web::http::header;
+/// use actix_web_opentelemetry::{CapturedHeaders, RequestTracing};
+///
+/// // records e.g. `http.request.header.x_request_id`
+/// let tracing = RequestTracing::new().with_captured_headers(
+///     CapturedHeaders::new()
+///         .request_header(header::HeaderName::from_static("x-request-id"))
+///         .response_header(header::CONTENT_TYPE),
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CapturedHeaders {
+    request: Vec<HeaderName>,
+    response: Vec<HeaderName>,
+}
+
+impl CapturedHeaders {
+    /// Capture no headers.
+    pub fn new() -> Self {
+        CapturedHeaders::default()
+    }
+
+    /// Record the request header `name`.
+    pub fn request_header(mut self, name: HeaderName) -> Self {
+        if !DENIED.contains(&name) {
+            self.request.push(name);
+        }
+        self
+    }
+
+    /// Record the response header `name`.
+    pub fn response_header(mut self, name: HeaderName) -> Self {
+        if !DENIED.contains(&name) {
+            self.response.push(name);
+        }
+        self
+    }
+
+    pub(crate) fn request_attributes(&self, headers: &HeaderMap) -> Vec<KeyValue> {
+        attributes("http.request.header", &self.request, headers)
+    }
+
+    pub(crate) fn response_attributes(&self, headers: &HeaderMap) -> Vec<KeyValue> {
+        attributes("http.response.header", &self.response, headers)
+    }
+}
+
+fn attributes(prefix: &str, names: &[HeaderName], headers: &HeaderMap) -> Vec<KeyValue> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let values: Vec<Cow<'static, str>> = headers
+                .get_all(name)
+                .filter_map(|value| value.to_str().ok())
+                .map(|value| value.to_string().into())
+                .collect();
+            if values.is_empty() {
+                return None;
+            }
+
+            let key = format!("{}.{}", prefix, name.as_str().replace('-', "_"));
+            Some(Key::new(key).array(values))
+        })
+        .collect()
+}
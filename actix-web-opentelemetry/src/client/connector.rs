@@ -0,0 +1,219 @@
+//! # Traced Connector
+use actix_tls::connect::{
+    tcp::TcpConnectorService, ConnectError, ConnectInfo, Resolve, Resolver, ResolverService,
+};
+use actix_web::{
+    dev::{always_ready, Service},
+    http::Uri,
+};
+use futures_util::future::LocalBoxFuture;
+use opentelemetry::{
+    trace::{SpanRef, TraceContextExt},
+    Context, KeyValue,
+};
+use opentelemetry_semantic_conventions::trace::{NET_PEER_IP, NET_PEER_NAME, NET_PEER_PORT};
+use std::{fmt, time::Instant};
+
+/// A TCP connector for [awc::Connector] recording the connection lifecycle on the span of
+/// the traced request.
+///
+/// Adds the span events `dns.resolved` and `tcp.connected`, each with its duration in
+/// milliseconds, or `dns.failed` and `tcp.failed`. Requests served from a pooled connection
+/// have none of these events.
+///
+/// TLS handshakes and connection pool checkouts are not recorded: awc performs both itself,
+/// around this connector, without exposing a hook to time them. Their duration is part of
+/// the client span but not broken out into events.
+///
+/// Example:
+/// ```no_run
+/// use actix_web_opentelemetry::{ClientExt, TracedConnector};
+/// use awc::{Client, Connector, error::SendRequestError};
+///
+/// async fn execute_request() -> Result<(), SendRequestError> {
+///     let client = Client::builder()
+///         .connector(Connector::new().connector(TracedConnector::new()))
+///         .finish();
+///
+///     let res = client.get("http://localhost:8080")
+///         .trace_request()
+///         .send()
+///         .await?;
+///
+///     println!("Response: {:?}", res);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct TracedConnector {
+    resolver: ResolverService,
+}
+
+impl fmt::Debug for TracedConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TracedConnector")
+    }
+}
+
+impl TracedConnector {
+    /// Create a connector resolving hosts with the system resolver.
+    pub fn new() -> Self {
+        TracedConnector::default()
+    }
+
+    /// Resolve hosts with a custom resolver.
+    pub fn with_resolver(mut self, resolver: impl Resolve + 'static) -> Self {
+        self.resolver = Resolver::custom(resolver).service();
+        self
+    }
+}
+
+impl Service<ConnectInfo<Uri>> for TracedConnector {
+    type Response = <TcpConnectorService as Service<ConnectInfo<Uri>>>::Response;
+    type Error = ConnectError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    always_ready!();
+
+    fn call(&self, req: ConnectInfo<Uri>) -> Self::Future {
+        // awc connects while polling the request, which runs in the context of the span
+        let cx = Context::current();
+        let resolver = self.resolver.clone();
+
+        Box::pin(async move {
+            let span = cx.span();
+
+            let timer = Instant::now();
+            let hostname = req.hostname().to_string();
+            let req = resolver
+                .call(req)
+                .await
+                .map_err(|err| record_failure(&span, "dns.failed", err, timer))?;
+            span.add_event(
+                "dns.resolved",
+                vec![
+                    NET_PEER_NAME.string(hostname),
+                    KeyValue::new("dns.addresses", req.addrs().len() as i64),
+                    elapsed_ms(timer),
+                ],
+            );
+
+            let timer = Instant::now();
+            let conn = TcpConnectorService::default()
+                .call(req)
+                .await
+                .map_err(|err| record_failure(&span, "tcp.failed", err, timer))?;
+            let mut attributes = vec![elapsed_ms(timer)];
+            if let Ok(addr) = conn.io_ref().peer_addr() {
+                attributes.push(NET_PEER_IP.string(addr.ip().to_string()));
+                attributes.push(NET_PEER_PORT.i64(addr.port() as i64));
+            }
+            span.add_event("tcp.connected", attributes);
+
+            Ok(conn)
+        })
+    }
+}
+
+fn elapsed_ms(timer: Instant) -> KeyValue {
+    KeyValue::new("duration_ms", timer.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn record_failure(
+    span: &SpanRef<'_>,
+    name: &'static str,
+    err: ConnectError,
+    timer: Instant,
+) -> ConnectError {
+    span.add_event(
+        name,
+        vec![
+            KeyValue::new("exception.message", err.to_string()),
+            elapsed_ms(timer),
+        ],
+    );
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web_telemetry_common::test_util::SpanCollector;
+    use opentelemetry::sdk::export::trace::SpanData;
+    use opentelemetry::trace::{Tracer as _, TracerProvider as _};
+    use std::net::TcpListener;
+
+    // Connect to `uri` in the context of a new span, returning the span.
+    async fn connect(uri: &str) -> (Result<(), ConnectError>, SpanData) {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let cx = Context::current_with_span(provider.tracer("test").start("request"));
+
+        let guard = cx.clone().attach();
+        let conn = TracedConnector::new().call(ConnectInfo::new(uri.parse::<Uri>().unwrap()));
+        drop(guard);
+        let result = conn.await.map(drop);
+        cx.span().end();
+
+        (result, collector.spans().remove(0))
+    }
+
+    fn events(span: &SpanData) -> Vec<&str> {
+        span.events
+            .iter()
+            .map(|event| event.name.as_ref())
+            .collect()
+    }
+
+    fn attribute(span: &SpanData, event: &str, key: &str) -> Option<String> {
+        span.events
+            .iter()
+            .find(|e| e.name == event)
+            .and_then(|e| e.attributes.iter().find(|kv| kv.key.as_str() == key))
+            .map(|kv| kv.value.to_string())
+    }
+
+    #[actix_web::test]
+    async fn records_resolution_and_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let (result, span) = connect(&format!("http://localhost:{}/", port)).await;
+        assert!(result.is_ok());
+        assert_eq!(events(&span), ["dns.resolved", "tcp.connected"]);
+        assert_eq!(
+            attribute(&span, "dns.resolved", "net.peer.name").as_deref(),
+            Some("localhost")
+        );
+        assert_eq!(
+            attribute(&span, "tcp.connected", "net.peer.ip").as_deref(),
+            Some("127.0.0.1")
+        );
+        assert_eq!(
+            attribute(&span, "tcp.connected", "net.peer.port"),
+            Some(port.to_string())
+        );
+        assert!(attribute(&span, "tcp.connected", "duration_ms").is_some());
+    }
+
+    #[actix_web::test]
+    async fn records_failed_connections() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let (result, span) = connect(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(result.is_err());
+        assert_eq!(events(&span), ["dns.resolved", "tcp.failed"]);
+        assert!(attribute(&span, "tcp.failed", "exception.message").is_some());
+    }
+
+    #[actix_web::test]
+    async fn records_failed_resolutions() {
+        let (result, span) = connect("http://unresolvable.invalid/").await;
+        assert!(matches!(result, Err(ConnectError::Resolver(_))));
+        assert_eq!(events(&span), ["dns.failed"]);
+        assert!(attribute(&span, "dns.failed", "exception.message").is_some());
+    }
+}
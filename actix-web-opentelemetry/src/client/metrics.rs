@@ -0,0 +1,300 @@
+//! # Client Metrics
+use super::AwcResult;
+use crate::RouteFormatter;
+use awc::http::{Method, Uri};
+use opentelemetry::{
+    metrics::{
+        noop::NoopMeterProvider, Counter, Meter, MeterProvider, UpDownCounter, ValueRecorder,
+    },
+    Key, KeyValue,
+};
+use std::{sync::Arc, time::SystemTime};
+
+/// Metrics of outbound awc requests, the client counterpart of
+/// [`RequestMetrics`](crate::RequestMetrics).
+///
+/// Records the `http.client.duration` in milliseconds, the number of requests and the
+/// number of requests in flight, labelled by method, host, route and status class (e.g.
+/// `2xx`, or `error` if no response was received).
+///
+/// The route is the request path formatted by the configured [`RouteFormatter`], falling
+/// back to the route formatter of the request. Without either the route is `unknown` to
+/// keep the number of label values bounded, unless the raw path is opted into with
+/// [`ClientMetrics::with_path_route`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use actix_web_opentelemetry::{ClientExt, ClientMetrics};
+/// use awc::{Client, error::SendRequestError};
+/// use opentelemetry::global;
+///
+/// async fn execute_request(client: &Client) -> Result<(), SendRequestError> {
+///     // Recorded on the prometheus exporter of the global meter provider, if installed
+///     let metrics = ClientMetrics::new(global::meter("actix_web"));
+///
+///     let res = client.get("http://localhost:8080")
+///         .trace_request()
+///         .with_metrics(metrics.clone())
+///         .send()
+///         .await?;
+///
+///     println!("Response: {:?}", res);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientMetrics {
+    route_formatter: Option<Arc<dyn RouteFormatter + Send + Sync + 'static>>,
+    path_route: bool,
+    http_client_duration: ValueRecorder<f64>,
+    http_client_requests_total: Counter<u64>,
+    http_client_active_requests: UpDownCounter<i64>,
+}
+
+impl Default for ClientMetrics {
+    fn default() -> Self {
+        let provider = NoopMeterProvider::new();
+        let meter = provider.meter("noop", None);
+        ClientMetrics::new(meter)
+    }
+}
+
+const ROUTE_KEY: Key = Key::from_static_str("route");
+const METHOD_KEY: Key = Key::from_static_str("method");
+const HOST_KEY: Key = Key::from_static_str("host");
+const STATUS_CLASS_KEY: Key = Key::from_static_str("status_class");
+const UNKNOWN_ROUTE: &str = "unknown";
+
+impl ClientMetrics {
+    /// Create a new [`ClientMetrics`] recording to `meter`.
+    pub fn new(meter: Meter) -> Self {
+        let http_client_duration = meter
+            .f64_value_recorder("http.client.duration")
+            .with_description("HTTP client request duration in milliseconds")
+            .init();
+
+        let http_client_requests_total = meter
+            .u64_counter("http.client.request_total")
+            .with_description("HTTP client requests per route")
+            .init();
+
+        let http_client_active_requests = meter
+            .i64_up_down_counter("http.client.active_requests")
+            .with_description("HTTP client requests in flight per route")
+            .init();
+
+        ClientMetrics {
+            route_formatter: None,
+            path_route: false,
+            http_client_duration,
+            http_client_requests_total,
+            http_client_active_requests,
+        }
+    }
+
+    /// Add a route formatter to template request paths, e.g. `/users/{id}`.
+    pub fn with_route_formatter<R>(mut self, route_formatter: R) -> Self
+    where
+        R: RouteFormatter + Send + Sync + 'static,
+    {
+        self.route_formatter = Some(Arc::new(route_formatter));
+        self
+    }
+
+    /// Use the request path as the route of requests without a route formatter.
+    ///
+    /// Every distinct path becomes a label value, so only use this if the paths requested
+    /// are bounded, e.g. contain no ids.
+    pub fn with_path_route(mut self) -> Self {
+        self.path_route = true;
+        self
+    }
+
+    pub(super) fn start(
+        self,
+        method: &Method,
+        uri: &Uri,
+        route_formatter: Option<&dyn RouteFormatter>,
+    ) -> ActiveRequest {
+        let labels = vec![
+            ROUTE_KEY.string(self.route(uri.path(), route_formatter)),
+            METHOD_KEY.string(method.as_str().to_string()),
+            HOST_KEY.string(uri.host().unwrap_or_default().to_string()),
+        ];
+        self.http_client_active_requests.add(1, &labels);
+
+        ActiveRequest {
+            metrics: self,
+            labels,
+            timer: SystemTime::now(),
+        }
+    }
+
+    fn route(&self, path: &str, route_formatter: Option<&dyn RouteFormatter>) -> String {
+        match (&self.route_formatter, route_formatter) {
+            (Some(formatter), _) => formatter.format(path),
+            (None, Some(formatter)) => formatter.format(path),
+            (None, None) if self.path_route => path.to_string(),
+            (None, None) => UNKNOWN_ROUTE.to_string(),
+        }
+    }
+}
+
+// A request in flight, no longer counted as active once dropped.
+pub(super) struct ActiveRequest {
+    metrics: ClientMetrics,
+    labels: Vec<KeyValue>,
+    timer: SystemTime,
+}
+
+impl ActiveRequest {
+    pub(super) fn finish(self, res: &AwcResult) {
+        let status_class = match res {
+            Ok(res) => format!("{}xx", res.status().as_u16() / 100),
+            Err(_) => "error".to_string(),
+        };
+        let mut labels = self.labels.clone();
+        labels.push(STATUS_CLASS_KEY.string(status_class));
+
+        self.metrics.http_client_requests_total.add(1, &labels);
+        self.metrics.http_client_duration.record(
+            self.timer
+                .elapsed()
+                .map(|t| t.as_secs_f64() * 1000.0)
+                .unwrap_or_default(),
+            &labels,
+        );
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.metrics
+            .http_client_active_requests
+            .add(-1, &self.labels);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClientExt;
+    use actix_web::{rt, web, App, HttpServer};
+    use futures_util::FutureExt as _;
+    use opentelemetry_prometheus::PrometheusExporter;
+    use prometheus::proto::MetricFamily;
+    use std::net::{SocketAddr, TcpListener};
+
+    #[derive(Debug)]
+    struct UsersFormatter;
+
+    impl RouteFormatter for UsersFormatter {
+        fn format(&self, _path: &str) -> String {
+            "/users/{id}".to_string()
+        }
+    }
+
+    #[test]
+    fn records_path_as_route_only_if_opted_in() {
+        let metrics = ClientMetrics::default();
+        assert_eq!(metrics.route("/users/1", None), "unknown");
+        assert_eq!(
+            metrics.route("/users/1", Some(&UsersFormatter)),
+            "/users/{id}"
+        );
+
+        let metrics = metrics.with_path_route();
+        assert_eq!(metrics.route("/users/1", None), "/users/1");
+        assert_eq!(
+            metrics.route("/users/1", Some(&UsersFormatter)),
+            "/users/{id}"
+        );
+    }
+
+    fn metrics() -> (ClientMetrics, PrometheusExporter) {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter = exporter.provider().unwrap().meter("test", None);
+        (ClientMetrics::new(meter), exporter)
+    }
+
+    fn family<'a>(families: &'a [MetricFamily], name: &str) -> &'a MetricFamily {
+        families
+            .iter()
+            .find(|family| family.get_name() == name)
+            .unwrap_or_else(|| panic!("no metric family {}", name))
+    }
+
+    fn label<'a>(family: &'a MetricFamily, name: &str) -> &'a str {
+        family.get_metric()[0]
+            .get_label()
+            .iter()
+            .find(|label| label.get_name() == name)
+            .map(|label| label.get_value())
+            .unwrap_or_default()
+    }
+
+    #[actix_web::test]
+    async fn records_duration_count_and_active_requests() {
+        let server = HttpServer::new(|| App::new().route("/", web::get().to(|| async { "ok" })))
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let addr: SocketAddr = server.addrs()[0];
+        rt::spawn(server.run());
+
+        let (metrics, exporter) = metrics();
+        let res = awc::Client::new()
+            .get(format!("http://{}/", addr))
+            .trace_request()
+            .with_metrics(metrics.with_path_route())
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+
+        let families = exporter.registry().gather();
+        let duration = family(&families, "http_client_duration");
+        assert_eq!(
+            duration.get_metric()[0].get_histogram().get_sample_count(),
+            1
+        );
+        let total = family(&families, "http_client_request_total");
+        assert_eq!(total.get_metric()[0].get_counter().get_value(), 1.0);
+        assert_eq!(label(total, "method"), "GET");
+        assert_eq!(label(total, "host"), "127.0.0.1");
+        assert_eq!(label(total, "route"), "/");
+        assert_eq!(label(total, "status_class"), "2xx");
+        let active = family(&families, "http_client_active_requests");
+        assert_eq!(active.get_metric()[0].get_gauge().get_value(), 0.0);
+        assert_eq!(label(active, "status_class"), "");
+    }
+
+    #[actix_web::test]
+    async fn decrements_active_requests_of_dropped_requests() {
+        // accepts connections through the backlog but never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (metrics, exporter) = metrics();
+
+        let mut request = Box::pin(
+            awc::Client::new()
+                .get(format!("http://{}/", listener.local_addr().unwrap()))
+                .trace_request()
+                .with_metrics(metrics)
+                .send(),
+        );
+        assert!((&mut request).now_or_never().is_none());
+        let families = exporter.registry().gather();
+        let active = family(&families, "http_client_active_requests");
+        assert_eq!(active.get_metric()[0].get_gauge().get_value(), 1.0);
+
+        drop(request);
+        let families = exporter.registry().gather();
+        let active = family(&families, "http_client_active_requests");
+        assert_eq!(active.get_metric()[0].get_gauge().get_value(), 0.0);
+        assert!(families
+            .iter()
+            .all(|family| family.get_name() != "http_client_request_total"));
+    }
+}
@@ -0,0 +1,697 @@
+//! # WebSocket Tracing
+use super::{
+    host_and_port, peer_attributes, record_err, ActixClientCarrier, SharedPropagator, SharedTracer,
+    UrlRedactor,
+};
+use crate::util::record_exception;
+use actix_codec::{Decoder, Encoder, Framed};
+use actix_http::{
+    error::HttpError,
+    ws::{Item, ProtocolError},
+};
+use actix_web::{
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, TryIntoHeaderValue},
+        Uri,
+    },
+    web::BytesMut,
+};
+use awc::{
+    error::WsClientError,
+    ws::{CloseReason, Codec, Frame, Message},
+    BoxedSocket, Client, ClientResponse,
+};
+use opentelemetry::{
+    global,
+    propagation::TextMapPropagator,
+    trace::{FutureExt as _, Link, SpanBuilder, SpanKind, StatusCode, TraceContextExt, Tracer},
+    Context, Key, KeyValue,
+};
+use opentelemetry_semantic_conventions::trace::{
+    HTTP_FLAVOR, HTTP_METHOD, HTTP_STATUS_CODE, HTTP_URL, MESSAGE_ID, MESSAGE_TYPE,
+    MESSAGE_UNCOMPRESSED_SIZE,
+};
+use std::{fmt, mem, net::SocketAddr, sync::Arc};
+
+const FRAME_TYPE_KEY: Key = Key::from_static_str("websocket.frame_type");
+const CLOSE_CODE_KEY: Key = Key::from_static_str("websocket.close_code");
+
+/// OpenTelemetry extensions for WebSocket connections of an [awc::Client].
+pub trait WebsocketClientExt {
+    /// Trace a WebSocket connection to `uri` using the current context.
+    ///
+    /// The handshake is recorded as a client span and the span context is injected into
+    /// the handshake request headers.
+    ///
+    /// Example:
+    /// ```no_run
+    /// use actix_web_opentelemetry::WebsocketClientExt;
+    /// use awc::{Client, error::WsClientError};
+    ///
+    /// async fn connect(client: &Client) -> Result<(), WsClientError> {
+    ///     let (res, framed) = client
+    ///         // Use `trace_ws` instead of `ws` to add instrumentation
+    ///         .trace_ws("ws://localhost:8080/ws")
+    ///         .connect()
+    ///         .await?;
+    ///
+    ///     println!("Response: {:?}", res);
+    ///     Ok(())
+    /// }
+    /// ```
+    fn trace_ws<U>(&self, uri: U) -> InstrumentedWebsocketsRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.trace_ws_with_context(uri, Context::current())
+    }
+
+    /// Trace a WebSocket connection to `uri` using the given span context.
+    fn trace_ws_with_context<U>(&self, uri: U, cx: Context) -> InstrumentedWebsocketsRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>;
+}
+
+impl WebsocketClientExt for Client {
+    fn trace_ws_with_context<U>(&self, uri: U, cx: Context) -> InstrumentedWebsocketsRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        // keep the URI to record it, awc does not expose it on the request
+        let (uri, err) = match Uri::try_from(uri) {
+            Ok(uri) => (uri, None),
+            Err(err) => (Uri::default(), Some(err.into())),
+        };
+
+        InstrumentedWebsocketsRequest {
+            cx,
+            attrs: Vec::new(),
+            request: self.ws::<Uri>(uri.clone()),
+            uri,
+            err,
+            url_redactor: UrlRedactor::default(),
+            tracer: None,
+            propagator: None,
+        }
+    }
+}
+
+/// A wrapper for the actix-web [awc::ws::WebsocketsRequest].
+#[derive(Debug)]
+pub struct InstrumentedWebsocketsRequest {
+    cx: Context,
+    attrs: Vec<KeyValue>,
+    request: awc::ws::WebsocketsRequest,
+    uri: Uri,
+    err: Option<HttpError>,
+    url_redactor: UrlRedactor,
+    tracer: Option<SharedTracer>,
+    propagator: Option<SharedPropagator>,
+}
+
+type WsResult<C> = Result<(ClientResponse, Framed<BoxedSocket, C>), WsClientError>;
+
+impl InstrumentedWebsocketsRequest {
+    /// Complete the traced handshake and connect to the WebSocket server.
+    ///
+    /// The span ends once the handshake is complete.
+    pub async fn connect(self) -> WsResult<Codec> {
+        let (res, framed, _) = self.handshake().await?;
+        Ok((res, framed))
+    }
+
+    /// Complete the traced handshake and connect to the WebSocket server, recording the
+    /// connection as a second span lasting until the connection is dropped.
+    ///
+    /// See [`InstrumentedWsCodec`] for the events and attributes of the connection span.
+    ///
+    /// Example:
+    /// ```no_run
+    /// use actix_web_opentelemetry::WebsocketClientExt;
+    /// use awc::{Client, error::WsClientError, ws::Message};
+    /// use futures_util::{SinkExt as _, StreamExt as _};
+    ///
+    /// async fn echo(client: &Client) -> Result<(), WsClientError> {
+    ///     let (_, mut framed) = client
+    ///         .trace_ws("ws://localhost:8080/ws")
+    ///         .connect_with_connection_span()
+    ///         .await?;
+    ///
+    ///     framed.send(Message::Text("hello".into())).await?;
+    ///     let reply = framed.next().await;
+    ///     println!("Reply: {:?}", reply);
+    ///
+    ///     // The connection span ends once `framed` is dropped
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn connect_with_connection_span(self) -> WsResult<InstrumentedWsCodec> {
+        let tracer = self.tracer.clone().unwrap_or_else(SharedTracer::global);
+        let parent_cx = self.cx.clone();
+        let name = format!("WebSocket {}{}", host_and_port(&self.uri), self.uri.path());
+        let mut attributes = vec![HTTP_URL.string(self.url_redactor.redact(&self.uri))];
+        attributes.extend(peer_attributes(&self.uri));
+
+        let (res, framed, handshake_cx) = self.handshake().await?;
+
+        let builder = SpanBuilder::from_name(name)
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .with_links(vec![Link::new(
+                handshake_cx.span().span_context().clone(),
+                Vec::new(),
+            )]);
+        let cx = tracer.0.start_with_context(builder, &parent_cx);
+
+        Ok((
+            res,
+            framed.into_map_codec(move |codec| InstrumentedWsCodec::new(codec, cx.clone())),
+        ))
+    }
+
+    async fn handshake(
+        mut self,
+    ) -> Result<(ClientResponse, Framed<BoxedSocket, Codec>, Context), WsClientError> {
+        if let Some(err) = self.err.take() {
+            return Err(err.into());
+        }
+
+        let tracer = self.tracer.take().unwrap_or_else(SharedTracer::global);
+        self.attrs.extend(
+            &mut [
+                HTTP_METHOD.string("GET"),
+                HTTP_URL.string(self.url_redactor.redact(&self.uri)),
+                HTTP_FLAVOR.string("1.1"),
+            ]
+            .into_iter(),
+        );
+        self.attrs.extend(peer_attributes(&self.uri));
+
+        let span_name = format!(
+            "GET {}{}{}",
+            self.uri
+                .scheme()
+                .map(|s| format!("{}://", s.as_str()))
+                .unwrap_or_default(),
+            host_and_port(&self.uri),
+            self.uri.path()
+        );
+        let builder = SpanBuilder::from_name(span_name)
+            .with_kind(SpanKind::Client)
+            .with_attributes(mem::take(&mut self.attrs));
+        let cx = tracer.0.start_with_context(builder, &self.cx);
+
+        let mut headers = HeaderMap::new();
+        {
+            let mut carrier = ActixClientCarrier::new(&mut headers, &cx);
+            match &self.propagator {
+                Some(propagator) => propagator.0.inject_context(&cx, &mut carrier),
+                None => global::get_text_map_propagator(|propagator| {
+                    propagator.inject_context(&cx, &mut carrier)
+                }),
+            }
+        }
+        let mut request = self.request;
+        for (name, value) in headers {
+            request = request.set_header(name, value);
+        }
+
+        // in the context of the span for `TracedConnector`
+        match request.connect().with_context(cx.clone()).await {
+            Ok((res, framed)) => {
+                let span = cx.span();
+                span.set_attribute(HTTP_STATUS_CODE.i64(res.status().as_u16() as i64));
+                span.end();
+                Ok((res, framed, cx))
+            }
+            Err(err) => {
+                if let WsClientError::InvalidResponseStatus(status) = &err {
+                    cx.span()
+                        .set_attribute(HTTP_STATUS_CODE.i64(status.as_u16() as i64));
+                }
+                record_err(&err, &cx);
+                Err(err)
+            }
+        }
+    }
+
+    /// Add additional attributes to the handshake span.
+    pub fn with_attributes(mut self, attrs: impl IntoIterator<Item = KeyValue>) -> Self {
+        self.attrs.extend(&mut attrs.into_iter());
+        self
+    }
+
+    /// Redact the URL recorded as `http.url` with the given [`UrlRedactor`].
+    pub fn with_url_redactor(mut self, url_redactor: UrlRedactor) -> Self {
+        self.url_redactor = url_redactor;
+        self
+    }
+
+    /// Start the spans with the given tracer instead of the global tracer provider.
+    pub fn with_tracer<T>(mut self, tracer: T) -> Self
+    where
+        T: Tracer + Send + Sync + 'static,
+        T::Span: Send + Sync + 'static,
+    {
+        self.tracer = Some(SharedTracer(Arc::new(tracer)));
+        self
+    }
+
+    /// Inject the span context with the given propagator instead of the global propagator.
+    pub fn with_propagator<P>(mut self, propagator: P) -> Self
+    where
+        P: TextMapPropagator + Send + Sync + 'static,
+    {
+        self.propagator = Some(SharedPropagator(Arc::new(propagator)));
+        self
+    }
+
+    /// Set the socket address of the server.
+    pub fn address(mut self, addr: SocketAddr) -> Self {
+        self.request = self.request.address(addr);
+        self
+    }
+
+    /// Set the supported WebSocket protocols.
+    pub fn protocols<U, V>(mut self, protos: U) -> Self
+    where
+        U: IntoIterator<Item = V>,
+        V: AsRef<str>,
+    {
+        self.request = self.request.protocols(protos);
+        self
+    }
+
+    /// Set the request `Origin`.
+    pub fn origin<V, E>(mut self, origin: V) -> Self
+    where
+        HeaderValue: TryFrom<V, Error = E>,
+        HttpError: From<E>,
+    {
+        self.request = self.request.origin(origin);
+        self
+    }
+
+    /// Set the max frame size, 64kB by default.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.request = self.request.max_frame_size(size);
+        self
+    }
+
+    /// Disable payload masking.
+    pub fn server_mode(mut self) -> Self {
+        self.request = self.request.server_mode();
+        self
+    }
+
+    /// Append a header.
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<HttpError>,
+        V: TryIntoHeaderValue,
+    {
+        self.request = self.request.header(key, value);
+        self
+    }
+
+    /// Insert a header, replacing an existing header.
+    pub fn set_header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<HttpError>,
+        V: TryIntoHeaderValue,
+    {
+        self.request = self.request.set_header(key, value);
+        self
+    }
+
+    /// Insert a header only if it is not yet set.
+    pub fn set_header_if_none<K, V>(mut self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<HttpError>,
+        V: TryIntoHeaderValue,
+    {
+        self.request = self.request.set_header_if_none(key, value);
+        self
+    }
+
+    /// Set HTTP basic authorization header.
+    pub fn basic_auth<U: fmt::Display>(mut self, username: U, password: Option<&str>) -> Self {
+        self.request = self.request.basic_auth(username, password);
+        self
+    }
+
+    /// Set HTTP bearer authentication header.
+    pub fn bearer_auth<T: fmt::Display>(mut self, token: T) -> Self {
+        self.request = self.request.bearer_auth(token);
+        self
+    }
+}
+
+/// The WebSocket [Codec] of a connection traced with
+/// [`InstrumentedWebsocketsRequest::connect_with_connection_span`].
+///
+/// Adds a `message` event with the `message.type` (`SENT` or `RECEIVED`), `message.id`,
+/// `message.uncompressed_size` and `websocket.frame_type` for every text, binary,
+/// continuation and close frame. Pings and pongs are not recorded.
+///
+/// Once the connection is dropped, the number of messages and payload bytes in each
+/// direction are recorded as `websocket.messages_sent`, `websocket.bytes_sent`,
+/// `websocket.messages_received` and `websocket.bytes_received` and the span ends.
+#[derive(Debug)]
+pub struct InstrumentedWsCodec {
+    codec: Codec,
+    cx: Context,
+    sent: Counts,
+    received: Counts,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    messages: i64,
+    bytes: i64,
+}
+
+impl InstrumentedWsCodec {
+    fn new(codec: Codec, cx: Context) -> Self {
+        InstrumentedWsCodec {
+            codec,
+            cx,
+            sent: Counts::default(),
+            received: Counts::default(),
+        }
+    }
+
+    fn record(&mut self, sent: bool, frame: Option<FrameInfo<'_>>) {
+        let (frame_type, size, close) = match frame {
+            Some(frame) => frame,
+            None => return,
+        };
+        let (message_type, counts) = if sent {
+            ("SENT", &mut self.sent)
+        } else {
+            ("RECEIVED", &mut self.received)
+        };
+        counts.messages += 1;
+        counts.bytes += size as i64;
+
+        let mut attributes = vec![
+            MESSAGE_TYPE.string(message_type),
+            MESSAGE_ID.i64(counts.messages),
+            MESSAGE_UNCOMPRESSED_SIZE.i64(size as i64),
+            FRAME_TYPE_KEY.string(frame_type),
+        ];
+        if let Some(reason) = close {
+            attributes.push(CLOSE_CODE_KEY.i64(u16::from(reason.code) as i64));
+        }
+        self.cx.span().add_event("message", attributes);
+    }
+}
+
+impl Encoder<Message> for InstrumentedWsCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.record(true, message_info(&item));
+        self.codec.encode(item, dst)
+    }
+}
+
+impl Decoder for InstrumentedWsCodec {
+    type Item = Frame;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.codec.decode(src) {
+            Ok(Some(frame)) => {
+                self.record(false, frame_info(&frame));
+                Ok(Some(frame))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => {
+                let span = self.cx.span();
+                record_exception(&span, Some(std::any::type_name::<ProtocolError>()), &err);
+                span.set_status(StatusCode::Error, format!("{:?}", err));
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for InstrumentedWsCodec {
+    fn drop(&mut self) {
+        let span = self.cx.span();
+        span.set_attribute(KeyValue::new("websocket.messages_sent", self.sent.messages));
+        span.set_attribute(KeyValue::new("websocket.bytes_sent", self.sent.bytes));
+        span.set_attribute(KeyValue::new(
+            "websocket.messages_received",
+            self.received.messages,
+        ));
+        span.set_attribute(KeyValue::new(
+            "websocket.bytes_received",
+            self.received.bytes,
+        ));
+        span.end();
+    }
+}
+
+// The type, payload size and close reason of a recorded frame.
+type FrameInfo<'a> = (&'static str, usize, Option<&'a CloseReason>);
+
+fn message_info(message: &Message) -> Option<FrameInfo<'_>> {
+    match message {
+        Message::Text(text) => Some(("text", text.len(), None)),
+        Message::Binary(bytes) => Some(("binary", bytes.len(), None)),
+        Message::Continuation(item) => Some(("continuation", item_len(item), None)),
+        Message::Close(reason) => Some(("close", 0, reason.as_ref())),
+        Message::Ping(_) | Message::Pong(_) | Message::Nop => None,
+    }
+}
+
+fn frame_info(frame: &Frame) -> Option<FrameInfo<'_>> {
+    match frame {
+        Frame::Text(bytes) => Some(("text", bytes.len(), None)),
+        Frame::Binary(bytes) => Some(("binary", bytes.len(), None)),
+        Frame::Continuation(item) => Some(("continuation", item_len(item), None)),
+        Frame::Close(reason) => Some(("close", 0, reason.as_ref())),
+        Frame::Ping(_) | Frame::Pong(_) => None,
+    }
+}
+
+fn item_len(item: &Item) -> usize {
+    match item {
+        Item::FirstText(bytes)
+        | Item::FirstBinary(bytes)
+        | Item::Continue(bytes)
+        | Item::Last(bytes) => bytes.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::{Actor, ActorContext, StreamHandler};
+    use actix_web::{rt, web, App, HttpRequest, HttpServer};
+    use actix_web_actors::ws;
+    use actix_web_telemetry_common::test_util::SpanCollector;
+    use futures_util::{SinkExt as _, StreamExt as _};
+    use opentelemetry::sdk::{export::trace::SpanData, propagation::TraceContextPropagator};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::Value;
+    use std::sync::Mutex;
+
+    // Echoes text and binary messages and close frames.
+    struct Echo;
+
+    impl Actor for Echo {
+        type Context = ws::WebsocketContext<Self>;
+    }
+
+    impl StreamHandler<Result<ws::Message, ProtocolError>> for Echo {
+        fn handle(&mut self, msg: Result<ws::Message, ProtocolError>, ctx: &mut Self::Context) {
+            match msg {
+                Ok(ws::Message::Text(text)) => ctx.text(text),
+                Ok(ws::Message::Binary(bytes)) => ctx.binary(bytes),
+                Ok(ws::Message::Close(reason)) => {
+                    ctx.close(reason);
+                    ctx.stop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Serves the echo server on `/ws`, returning its address and the `traceparent` headers
+    // of the handshake requests.
+    fn echo_server() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let traceparents = Arc::new(Mutex::new(Vec::new()));
+        let received = traceparents.clone();
+        let server = HttpServer::new(move || {
+            let received = received.clone();
+            App::new().route(
+                "/ws",
+                web::get().to(move |req: HttpRequest, stream: web::Payload| {
+                    if let Some(traceparent) = req.headers().get("traceparent") {
+                        let traceparent = traceparent.to_str().unwrap().to_string();
+                        received.lock().unwrap().push(traceparent);
+                    }
+                    async move { ws::start(Echo, &req, stream) }
+                }),
+            )
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        rt::spawn(server.run());
+        (addr, traceparents)
+    }
+
+    fn attribute(span: &SpanData, key: &'static str) -> Option<Value> {
+        span.attributes.get(&Key::new(key)).cloned()
+    }
+
+    #[actix_web::test]
+    async fn traces_handshakes_and_injects_their_context() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let (addr, traceparents) = echo_server();
+
+        let (res, _framed) = Client::new()
+            .trace_ws(format!("ws://{}/ws", addr))
+            .with_tracer(provider.tracer("test"))
+            .with_propagator(TraceContextPropagator::new())
+            .connect()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 101);
+
+        let spans = collector.spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, format!("GET ws://{}/ws", addr));
+        assert_eq!(spans[0].span_kind, SpanKind::Client);
+        assert_eq!(spans[0].status_code, StatusCode::Unset);
+        assert_eq!(
+            attribute(&spans[0], "http.status_code"),
+            Some(Value::I64(101))
+        );
+        let span_context = &spans[0].span_context;
+        assert_eq!(
+            *traceparents.lock().unwrap(),
+            [format!(
+                "00-{}-{}-01",
+                span_context.trace_id(),
+                span_context.span_id()
+            )]
+        );
+    }
+
+    #[actix_web::test]
+    async fn records_failed_handshakes() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let (addr, _) = echo_server();
+
+        let res = Client::new()
+            .trace_ws(format!("ws://{}/missing", addr))
+            .with_tracer(provider.tracer("test"))
+            .connect()
+            .await;
+        assert!(matches!(res, Err(WsClientError::InvalidResponseStatus(_))));
+
+        let spans = collector.spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].status_code, StatusCode::Error);
+        assert_eq!(
+            attribute(&spans[0], "http.status_code"),
+            Some(Value::I64(404))
+        );
+    }
+
+    #[actix_web::test]
+    async fn records_messages_of_connection_spans() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let (addr, _) = echo_server();
+
+        let (_, mut framed) = Client::new()
+            .trace_ws(format!("ws://{}/ws", addr))
+            .with_tracer(provider.tracer("test"))
+            .connect_with_connection_span()
+            .await
+            .unwrap();
+        framed.send(Message::Text("hello".into())).await.unwrap();
+        assert!(matches!(framed.next().await, Some(Ok(Frame::Text(_)))));
+        framed.send(Message::Close(None)).await.unwrap();
+        assert!(matches!(framed.next().await, Some(Ok(Frame::Close(_)))));
+        // the connection span ends once the codec is dropped
+        assert_eq!(collector.spans().len(), 1);
+        drop(framed);
+
+        let spans = collector.spans();
+        assert_eq!(spans.len(), 2);
+        let (handshake, connection) = (&spans[0], &spans[1]);
+        assert_eq!(connection.name, format!("WebSocket {}/ws", addr));
+        assert_eq!(
+            connection.links.iter().next().unwrap().span_context(),
+            &handshake.span_context
+        );
+
+        let messages: Vec<_> = connection
+            .events
+            .iter()
+            .map(|event| {
+                let value = |key: &str| {
+                    event
+                        .attributes
+                        .iter()
+                        .find(|kv| kv.key.as_str() == key)
+                        .map(|kv| kv.value.to_string())
+                        .unwrap()
+                };
+                (
+                    value("message.type"),
+                    value("message.id"),
+                    value("websocket.frame_type"),
+                    value("message.uncompressed_size"),
+                )
+            })
+            .collect();
+        let message = |message_type: &str, id: &str, frame_type: &str, size: &str| {
+            (
+                message_type.to_string(),
+                id.to_string(),
+                frame_type.to_string(),
+                size.to_string(),
+            )
+        };
+        assert_eq!(
+            messages,
+            [
+                message("SENT", "1", "text", "5"),
+                message("RECEIVED", "1", "text", "5"),
+                message("SENT", "2", "close", "0"),
+                message("RECEIVED", "2", "close", "0"),
+            ]
+        );
+        for (key, count) in [
+            ("websocket.messages_sent", 2),
+            ("websocket.bytes_sent", 5),
+            ("websocket.messages_received", 2),
+            ("websocket.bytes_received", 5),
+        ] {
+            assert_eq!(
+                attribute(connection, key),
+                Some(Value::I64(count)),
+                "{}",
+                key
+            );
+        }
+    }
+}
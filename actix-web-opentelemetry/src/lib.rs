@@ -7,6 +7,7 @@
 //! The `awc` feature allows you to instrument client requests made by the [awc] crate.
 //!
 //! * Client requests can be traced by using the [`ClientExt::trace_request`] method.
+//! * WebSocket connections can be traced by using the [`WebsocketClientExt::trace_ws`] method.
 //!
 //! The `metrics` feature allows you to expose request metrics to [Prometheus].
 //!
@@ -127,6 +128,7 @@
 #![cfg_attr(test, deny(warnings))]
 #![cfg_attr(docsrs, feature(doc_cfg), deny(broken_intra_doc_links))]
 
+mod captured_headers;
 #[cfg(feature = "awc")]
 mod client;
 mod middleware;
@@ -134,9 +136,25 @@ pub(crate) mod util;
 
 #[cfg(feature = "awc")]
 #[cfg_attr(docsrs, doc(cfg(feature = "awc")))]
-pub use client::{ClientExt, InstrumentedClientRequest};
+pub use client::{
+    ActixClientCarrier, ClientExt, ClientTracing, InstrumentedClientRequest,
+    InstrumentedWebsocketsRequest, InstrumentedWsCodec, TracedConnector, UrlRedactor,
+    WebsocketClientExt,
+};
 
+#[cfg(all(feature = "awc", feature = "metrics"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "awc", feature = "metrics"))))]
+pub use client::ClientMetrics;
+
+pub use actix_web_telemetry_common::{
+    DefaultStatusClassifier, StatusClassifier, TraceResponse, TrustedProxies,
+};
 #[cfg(feature = "metrics")]
 #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
 pub use middleware::metrics::{RequestMetrics, RequestMetricsMiddleware};
-pub use {middleware::route_formatter::RouteFormatter, middleware::trace::RequestTracing};
+pub use {
+    captured_headers::CapturedHeaders,
+    middleware::route_formatter::RouteFormatter,
+    middleware::span_namer::{MethodRouteSpanNamer, SpanNamer},
+    middleware::trace::{RequestTracing, RequestTracingBody},
+};
@@ -46,7 +46,7 @@ pub struct RequestMetrics<F>
 where
     F: Fn(&dev::ServiceRequest) -> bool + Send + Clone,
 {
-    exporter: PrometheusExporter,
+    exporter: Option<PrometheusExporter>,
     route_formatter: Option<Arc<dyn RouteFormatter + Send + Sync + 'static>>,
     should_render_metrics: Option<F>,
     http_requests_total: Counter<u64>,
@@ -88,12 +88,20 @@ where
     F: Fn(&dev::ServiceRequest) -> bool + Send + Clone,
 {
     /// Create a new [`RequestMetrics`]
+    ///
+    /// A default prometheus exporter is only installed if `should_render_metrics`
+    /// is set and no `exporter` is given, so metrics pushed through another
+    /// pipeline (e.g. OTLP) are left untouched.
     pub fn new(
         meter: Meter,
         should_render_metrics: Option<F>,
         exporter: Option<PrometheusExporter>,
     ) -> Self {
-        let exporter = exporter.unwrap_or_else(|| opentelemetry_prometheus::exporter().init());
+        let exporter = exporter.or_else(|| {
+            should_render_metrics
+                .as_ref()
+                .map(|_| opentelemetry_prometheus::exporter().init())
+        });
         let http_requests_total = meter
             .u64_counter("http_request_total")
             .with_description("HTTP requests per route")
@@ -125,8 +133,12 @@ where
     }
 
     fn metrics(&self) -> String {
+        let exporter = match &self.exporter {
+            Some(exporter) => exporter,
+            None => return String::new(),
+        };
         let encoder = TextEncoder::new();
-        let metric_families = self.exporter.registry().gather();
+        let metric_families = exporter.registry().gather();
         let mut buf = Vec::new();
         if let Err(err) = encoder.encode(&metric_families[..], &mut buf) {
             global::handle_error(MetricsError::Other(err.to_string()));
@@ -2,4 +2,5 @@
 #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
 pub(crate) mod metrics;
 pub(crate) mod route_formatter;
+pub(crate) mod span_namer;
 pub(crate) mod trace;
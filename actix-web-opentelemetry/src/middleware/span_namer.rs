@@ -0,0 +1,87 @@
+//! # Span Namer
+//!
+//! Name server spans from requests.
+use actix_web::dev::ServiceRequest;
+use std::fmt;
+
+/// Interface for naming the span of a request traced by
+/// [`RequestTracing`](crate::RequestTracing).
+///
+/// By default spans are named after the route of the request, or `default` if no resource
+/// matched. [`MethodRouteSpanNamer`] names them e.g. `GET /users/{id}` instead.
+///
+/// # Custom Span Namer Examples
+///
+/// ```
+/// use actix_web::dev::ServiceRequest;
+/// use actix_web_opentelemetry::{RequestTracing, SpanNamer};
+///
+/// // Prefix span names with the API version header.
+/// struct VersionedSpanNamer;
+///
+/// impl SpanNamer for VersionedSpanNamer {
+///     fn name(&self, req: &ServiceRequest, route: Option<&str>) -> String {
+///         let version = req
+///             .headers()
+///             .get("x-api-version")
+///             .and_then(|version| version.to_str().ok())
+///             .unwrap_or("v1");
+///         format!("{} {} {}", version, req.method(), route.unwrap_or("unmatched"))
+///     }
+/// }
+///
+/// let tracing = RequestTracing::new().with_span_namer(VersionedSpanNamer);
+/// ```
+///
+/// Closures can name spans as well:
+///
+/// ```
+/// use actix_web::dev::ServiceRequest;
+/// use actix_web_opentelemetry::RequestTracing;
+///
+/// let tracing = RequestTracing::new().with_span_namer(|req: &ServiceRequest, route: Option<&str>| {
+///     format!("{} {}", req.method(), route.unwrap_or("unmatched"))
+/// });
+/// ```
+pub trait SpanNamer {
+    /// Name the span of `req`, with the formatted `route` if a resource matched.
+    fn name(&self, req: &ServiceRequest, route: Option<&str>) -> String;
+}
+
+impl<F> SpanNamer for F
+where
+    F: Fn(&ServiceRequest, Option<&str>) -> String,
+{
+    fn name(&self, req: &ServiceRequest, route: Option<&str>) -> String {
+        self(req, route)
+    }
+}
+
+impl fmt::Debug for dyn SpanNamer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpanNamer")
+    }
+}
+
+/// Names spans `{method} {route}`, e.g. `GET /users/{id}`, or `HTTP {method}` if no
+/// resource matched, following the semantic conventions for HTTP server spans.
+///
+/// # Examples
+///
+/// ```
+/// use actix_web_opentelemetry::{MethodRouteSpanNamer, RequestTracing};
+///
+/// // unmatched requests are recorded as e.g. `HTTP GET` instead of `default`
+/// let tracing = RequestTracing::new().with_span_namer(MethodRouteSpanNamer);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MethodRouteSpanNamer;
+
+impl SpanNamer for MethodRouteSpanNamer {
+    fn name(&self, req: &ServiceRequest, route: Option<&str>) -> String {
+        match route {
+            Some(route) => format!("{} {}", req.method(), route),
+            None => format!("HTTP {}", req.method()),
+        }
+    }
+}
@@ -1,23 +1,48 @@
-use super::route_formatter::RouteFormatter;
-use crate::util::{http_flavor, http_method_str, http_scheme};
+use super::{route_formatter::RouteFormatter, span_namer::SpanNamer};
+use crate::util::{
+    http_flavor, http_method_str, http_scheme, record_actix_exception, record_exception,
+    traceparent, SharedTracer,
+};
+use crate::{CapturedHeaders, StatusClassifier, TraceResponse, TrustedProxies};
 use actix_web::{
+    body::{BodySize, MessageBody},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    http::header::{self, HeaderMap},
+    http::{
+        header::{self, HeaderMap},
+        Method,
+    },
+    web::Bytes,
     Error,
 };
-use futures_util::future::{ok, FutureExt as _, LocalBoxFuture, Ready};
+use actix_web_telemetry_common::split_host;
+use futures_util::{
+    future::{ok, FutureExt as _, LocalBoxFuture, Ready},
+    ready,
+};
 use opentelemetry::{
     global,
     propagation::Extractor,
     trace::{
-        FutureExt as OtelFutureExt, SpanKind, StatusCode, TraceContextExt, Tracer, TracerProvider,
+        FutureExt as OtelFutureExt, SpanBuilder, SpanKind, SpanRef, StatusCode, TraceContextExt,
+        Tracer, TracerProvider,
     },
+    Context, KeyValue,
 };
 use opentelemetry_semantic_conventions::trace::{
-    HTTP_CLIENT_IP, HTTP_FLAVOR, HTTP_HOST, HTTP_METHOD, HTTP_ROUTE, HTTP_SCHEME, HTTP_SERVER_NAME,
-    HTTP_STATUS_CODE, HTTP_TARGET, HTTP_USER_AGENT, NET_HOST_PORT, NET_PEER_IP,
+    HTTP_CLIENT_IP, HTTP_FLAVOR, HTTP_HOST, HTTP_METHOD, HTTP_RESPONSE_CONTENT_LENGTH, HTTP_ROUTE,
+    HTTP_SCHEME, HTTP_SERVER_NAME, HTTP_STATUS_CODE, HTTP_TARGET, HTTP_USER_AGENT, NET_HOST_NAME,
+    NET_HOST_PORT, NET_PEER_IP, NET_PEER_PORT,
+};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    borrow::Cow,
+    error::Error as StdError,
+    fmt,
+    pin::Pin,
+    rc::Rc,
+    sync::Arc,
+    task::{self, Poll},
 };
-use std::{borrow::Cow, rc::Rc, task::Poll};
 
 /// Request tracing middleware.
 ///
@@ -50,7 +75,94 @@ use std::{borrow::Cow, rc::Rc, task::Poll};
 ///```
 #[derive(Default, Debug)]
 pub struct RequestTracing {
+    tracer: Option<SharedTracer>,
     route_formatter: Option<Rc<dyn RouteFormatter + 'static>>,
+    span_namer: Option<Rc<dyn SpanNamer + 'static>>,
+    status_classifier: Option<Rc<dyn StatusClassifier + 'static>>,
+    captured_headers: Rc<CapturedHeaders>,
+    trace_response: Option<TraceResponse>,
+    trusted_proxies: Option<Rc<TrustedProxies>>,
+    request_filter: Rc<RequestFilter>,
+    hooks: Rc<Hooks>,
+}
+
+type RequestHook = Rc<dyn Fn(&ServiceRequest, &mut Vec<KeyValue>)>;
+type ResponseHook = Rc<dyn Fn(&ServiceResponse<()>, &SpanRef<'_>)>;
+
+// Callbacks adding attributes to spans.
+#[derive(Clone, Default)]
+struct Hooks {
+    on_request: Vec<RequestHook>,
+    on_response: Vec<ResponseHook>,
+}
+
+impl Hooks {
+    // The hooks only see the response head, as they are not generic over the body.
+    fn on_response<B>(&self, res: ServiceResponse<B>, span: &SpanRef<'_>) -> ServiceResponse<B> {
+        if self.on_response.is_empty() {
+            return res;
+        }
+
+        let (req, res) = res.into_parts();
+        let (res, body) = res.into_parts();
+        let head = ServiceResponse::new(req, res);
+        for hook in &self.on_response {
+            hook(&head, span);
+        }
+        let (req, res) = head.into_parts();
+        ServiceResponse::new(req, res.set_body(body))
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_request", &self.on_request.len())
+            .field("on_response", &self.on_response.len())
+            .finish()
+    }
+}
+
+type RequestPredicate = Rc<dyn Fn(&ServiceRequest) -> bool>;
+
+// Requests excluded from tracing.
+#[derive(Clone, Default)]
+struct RequestFilter {
+    paths: Vec<Cow<'static, str>>,
+    path_prefixes: Vec<Cow<'static, str>>,
+    methods: Vec<Method>,
+    predicate: Option<RequestPredicate>,
+}
+
+impl RequestFilter {
+    fn should_trace(&self, req: &ServiceRequest) -> bool {
+        let path = req.path();
+        if self.paths.iter().any(|excluded| excluded == path)
+            || self
+                .path_prefixes
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_ref()))
+            || self.methods.contains(req.method())
+        {
+            return false;
+        }
+
+        match &self.predicate {
+            Some(predicate) => predicate(req),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for RequestFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestFilter")
+            .field("paths", &self.paths)
+            .field("path_prefixes", &self.path_prefixes)
+            .field("methods", &self.methods)
+            .field("predicate", &self.predicate.as_ref().map(|_| "Fn"))
+            .finish()
+    }
 }
 
 impl RequestTracing {
@@ -95,57 +207,294 @@ impl RequestTracing {
     pub fn with_formatter<T: RouteFormatter + 'static>(route_formatter: T) -> Self {
         RequestTracing {
             route_formatter: Some(Rc::new(route_formatter)),
+            ..RequestTracing::default()
         }
     }
+
+    /// Start spans with a tracer of the given provider instead of the global tracer
+    /// provider.
+    ///
+    /// The provider must be kept alive for as long as requests are traced, as tracers do
+    /// not keep their provider alive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use actix_web_opentelemetry::RequestTracing;
+    /// use opentelemetry::sdk::trace::TracerProvider;
+    ///
+    /// // e.g. to send the spans of this app to a separate exporter
+    /// let provider = TracerProvider::builder().build();
+    /// let tracing = RequestTracing::new().with_tracer_provider(&provider);
+    /// ```
+    pub fn with_tracer_provider<P>(self, tracer_provider: &P) -> Self
+    where
+        P: TracerProvider,
+        P::Tracer: Send + Sync + 'static,
+        <P::Tracer as Tracer>::Span: Send + Sync + 'static,
+    {
+        self.with_tracer(versioned_tracer(tracer_provider))
+    }
+
+    /// Start spans with the given tracer, e.g. a
+    /// [`BoxedTracer`](opentelemetry::global::BoxedTracer), instead of a tracer of the
+    /// global tracer provider.
+    pub fn with_tracer<T>(mut self, tracer: T) -> Self
+    where
+        T: Tracer + Send + Sync + 'static,
+        T::Span: Send + Sync + 'static,
+    {
+        self.tracer = Some(SharedTracer(Arc::new(tracer)));
+        self
+    }
+
+    /// Name spans with the given [`SpanNamer`] instead of the route.
+    pub fn with_span_namer<T: SpanNamer + 'static>(mut self, span_namer: T) -> Self {
+        self.span_namer = Some(Rc::new(span_namer));
+        self
+    }
+
+    /// Decide which requests are recorded as errors with the given [`StatusClassifier`], e.g.
+    /// [`DefaultStatusClassifier`](crate::DefaultStatusClassifier).
+    ///
+    /// By default, server errors (5xx) are recorded as errors with the reason phrase of their
+    /// status, and errors returned by the wrapped service instead of a response with their
+    /// `Debug` representation, whatever their status.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use actix_web_opentelemetry::{DefaultStatusClassifier, RequestTracing};
+    ///
+    /// let tracing = RequestTracing::new().with_status_classifier(DefaultStatusClassifier);
+    /// ```
+    pub fn with_status_classifier<T: StatusClassifier + 'static>(
+        mut self,
+        status_classifier: T,
+    ) -> Self {
+        self.status_classifier = Some(Rc::new(status_classifier));
+        self
+    }
+
+    /// Record the given request and response headers as span attributes.
+    pub fn with_captured_headers(mut self, captured_headers: CapturedHeaders) -> Self {
+        self.captured_headers = Rc::new(captured_headers);
+        self
+    }
+
+    /// Inject the span context into responses with the given [`TraceResponse`] headers.
+    ///
+    /// Errors returned by the wrapped service are replaced, see
+    /// [`TraceResponse`](TraceResponse#errors).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use actix_web_opentelemetry::{RequestTracing, TraceResponse};
+    ///
+    /// let tracing = RequestTracing::new().with_trace_response(
+    ///     TraceResponse::new().server_timing(true),
+    /// );
+    /// ```
+    pub fn with_trace_response(mut self, trace_response: TraceResponse) -> Self {
+        self.trace_response = Some(trace_response);
+        self
+    }
+
+    /// Record the client IP reported by the given [`TrustedProxies`] only, instead of
+    /// trusting the forwarding headers of any peer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use actix_web_opentelemetry::{RequestTracing, TrustedProxies};
+    ///
+    /// let tracing = RequestTracing::new()
+    ///     .with_trusted_proxies(TrustedProxies::new().trust_private_networks());
+    /// ```
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(Rc::new(trusted_proxies));
+        self
+    }
+
+    /// Only trace requests for which `filter` returns `true`.
+    ///
+    /// Requests that are not traced still run in the context propagated by the client, so
+    /// spans created while handling them keep their parent.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use actix_web::{dev::ServiceRequest, http, web, App, HttpServer};
+    /// use actix_web_opentelemetry::RequestTracing;
+    ///
+    /// # #[actix_web::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// HttpServer::new(|| {
+    ///     App::new()
+    ///         .wrap(
+    ///             RequestTracing::new()
+    ///                 .exclude_path("/healthz")
+    ///                 .exclude_path_prefix("/static/")
+    ///                 .exclude_method(http::Method::OPTIONS)
+    ///                 .with_filter(|req: &ServiceRequest| {
+    ///                     !req.headers().contains_key("x-synthetic-check")
+    ///                 }),
+    ///         )
+    ///         .service(web::resource("/").to(|| async { "ok" }))
+    /// })
+    /// .bind("127.0.0.1:8080")?
+    /// .run()
+    /// .await
+    /// # }
+    /// ```
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + 'static,
+    {
+        self.request_filter_mut().predicate = Some(Rc::new(filter));
+        self
+    }
+
+    /// Do not trace requests for exactly `path`, e.g. `/healthz`.
+    pub fn exclude_path(mut self, path: impl Into<Cow<'static, str>>) -> Self {
+        self.request_filter_mut().paths.push(path.into());
+        self
+    }
+
+    /// Do not trace requests for paths starting with `prefix`, e.g. `/static/`.
+    pub fn exclude_path_prefix(mut self, prefix: impl Into<Cow<'static, str>>) -> Self {
+        self.request_filter_mut().path_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Do not trace requests with the given `method`, e.g. `OPTIONS`.
+    pub fn exclude_method(mut self, method: Method) -> Self {
+        self.request_filter_mut().methods.push(method);
+        self
+    }
+
+    // Copied if already shared with a middleware, which keeps its filter.
+    fn request_filter_mut(&mut self) -> &mut RequestFilter {
+        Rc::make_mut(&mut self.request_filter)
+    }
+
+    /// Add attributes to the span of each request before it is started.
+    ///
+    /// Hooks run after the standard attributes are collected, in the order they were
+    /// added, and are not called for requests that are not traced.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use actix_web::{dev::ServiceRequest, web, App, HttpServer};
+    /// use actix_web_opentelemetry::RequestTracing;
+    /// use opentelemetry::KeyValue;
+    ///
+    /// # #[actix_web::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// HttpServer::new(|| {
+    ///     App::new()
+    ///         .wrap(
+    ///             RequestTracing::new()
+    ///                 .on_request(|req: &ServiceRequest, attributes: &mut Vec<KeyValue>| {
+    ///                     if let Some(tenant) = req.headers().get("x-tenant-id") {
+    ///                         let tenant = tenant.to_str().unwrap_or_default().to_string();
+    ///                         attributes.push(KeyValue::new("tenant.id", tenant));
+    ///                     }
+    ///                 })
+    ///                 .on_response(|res, span| {
+    ///                     if let Some(cache) = res.headers().get("x-cache") {
+    ///                         let cache = cache.to_str().unwrap_or_default().to_string();
+    ///                         span.set_attribute(KeyValue::new("cache.status", cache));
+    ///                     }
+    ///                 }),
+    ///         )
+    ///         .service(web::resource("/").to(|| async { "ok" }))
+    /// })
+    /// .bind("127.0.0.1:8080")?
+    /// .run()
+    /// .await
+    /// # }
+    /// ```
+    pub fn on_request<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ServiceRequest, &mut Vec<KeyValue>) + 'static,
+    {
+        self.hooks_mut().on_request.push(Rc::new(hook));
+        self
+    }
+
+    /// Add attributes to the span of each request once the response is returned.
+    ///
+    /// The response is passed without its body, which is not sent yet. Hooks are not
+    /// called if the service returns an error instead of a response.
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ServiceResponse<()>, &SpanRef<'_>) + 'static,
+    {
+        self.hooks_mut().on_response.push(Rc::new(hook));
+        self
+    }
+
+    // Copied if already shared with a middleware, which keeps its hooks.
+    fn hooks_mut(&mut self) -> &mut Hooks {
+        Rc::make_mut(&mut self.hooks)
+    }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for RequestTracing
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<RequestTracingBody<B>>;
     type Error = Error;
     type Transform = RequestTracingMiddleware<S>;
     type InitError = ();
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(RequestTracingMiddleware::new(
-            global::tracer_provider().versioned_tracer(
-                "actix-web-opentelemetry",
-                Some(env!("CARGO_PKG_VERSION")),
-                None,
-            ),
-            service,
-            self.route_formatter.clone(),
-        ))
+        let tracer = self.tracer.clone().unwrap_or_else(|| {
+            SharedTracer(Arc::new(versioned_tracer(&global::tracer_provider())))
+        });
+        ok(RequestTracingMiddleware::new(tracer, service, self))
     }
 }
 
 #[derive(Debug)]
 pub struct RequestTracingMiddleware<S> {
-    tracer: global::BoxedTracer,
+    tracer: SharedTracer,
     service: S,
     route_formatter: Option<Rc<dyn RouteFormatter>>,
+    span_namer: Option<Rc<dyn SpanNamer>>,
+    status_classifier: Option<Rc<dyn StatusClassifier>>,
+    captured_headers: Rc<CapturedHeaders>,
+    trace_response: Option<TraceResponse>,
+    trusted_proxies: Option<Rc<TrustedProxies>>,
+    request_filter: Rc<RequestFilter>,
+    hooks: Rc<Hooks>,
 }
 
 impl<S, B> RequestTracingMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
-    fn new(
-        tracer: global::BoxedTracer,
-        service: S,
-        route_formatter: Option<Rc<dyn RouteFormatter>>,
-    ) -> Self {
+    fn new(tracer: SharedTracer, service: S, config: &RequestTracing) -> Self {
         RequestTracingMiddleware {
             tracer,
             service,
-            route_formatter,
+            route_formatter: config.route_formatter.clone(),
+            span_namer: config.span_namer.clone(),
+            status_classifier: config.status_classifier.clone(),
+            captured_headers: config.captured_headers.clone(),
+            trace_response: config.trace_response,
+            trusted_proxies: config.trusted_proxies.clone(),
+            request_filter: config.request_filter.clone(),
+            hooks: config.hooks.clone(),
         }
     }
 }
@@ -154,9 +503,9 @@ impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<RequestTracingBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -168,15 +517,32 @@ where
         let parent_context = global::get_text_map_propagator(|propagator| {
             propagator.extract(&RequestHeaderCarrier::new(req.headers_mut()))
         });
-        let mut http_route: Cow<'static, str> = req
-            .match_pattern()
+        if !self.request_filter.should_trace(&req) {
+            #[cfg(feature = "sync-middleware")]
+            let _attachment = parent_context.clone().attach();
+            let fut = self.service.call(req).with_context(parent_context);
+            return Box::pin(fut.map(|res| {
+                res.map(|res| res.map_body(|_, body| RequestTracingBody::new(body, None)))
+            }));
+        }
+
+        let match_pattern = req.match_pattern();
+        let mut http_route: Cow<'static, str> = match_pattern
+            .clone()
             .map(Into::into)
             .unwrap_or_else(|| "default".into());
         if let Some(formatter) = &self.route_formatter {
             http_route = formatter.format(&http_route).into();
         }
+        let span_name: Cow<'static, str> = match &self.span_namer {
+            Some(namer) => {
+                let route = match_pattern.as_ref().map(|_| http_route.as_ref());
+                namer.name(&req, route).into()
+            }
+            None => http_route.clone(),
+        };
         let conn_info = req.connection_info();
-        let mut builder = self.tracer.span_builder(http_route.clone());
+        let mut builder = SpanBuilder::from_name(span_name);
         builder.span_kind = Some(SpanKind::Server);
         let mut attributes = Vec::with_capacity(11);
         attributes.push(HTTP_METHOD.string(http_method_str(req.method())));
@@ -189,14 +555,11 @@ where
         if server_name != conn_info.host() {
             attributes.push(HTTP_SERVER_NAME.string(server_name.to_string()));
         }
-        if let Some(port) = conn_info
-            .host()
-            .split_terminator(':')
-            .nth(1)
-            .and_then(|port| port.parse().ok())
-        {
-            attributes.push(NET_HOST_PORT.i64(port))
-        }
+        let (host_name, host_port) = split_host(conn_info.host());
+        attributes.push(NET_HOST_NAME.string(host_name.to_string()));
+        // without a port in the host, the port the server is bound to
+        let host_port = host_port.unwrap_or_else(|| req.app_config().local_addr().port());
+        attributes.push(NET_HOST_PORT.i64(host_port as i64));
         if let Some(path) = req.uri().path_and_query() {
             attributes.push(HTTP_TARGET.string(path.as_str().to_string()))
         }
@@ -207,55 +570,192 @@ where
         {
             attributes.push(HTTP_USER_AGENT.string(user_agent.to_string()))
         }
-        let remote_addr = conn_info.realip_remote_addr();
-        if let Some(remote) = remote_addr {
-            attributes.push(HTTP_CLIENT_IP.string(remote.to_string()))
-        }
-        if let Some(peer_addr) = req.peer_addr().map(|socket| socket.to_string()) {
-            if Some(peer_addr.as_str()) != remote_addr {
-                // Client is going through a proxy
-                attributes.push(NET_PEER_IP.string(peer_addr))
+        match (&self.trusted_proxies, req.peer_addr()) {
+            (Some(trusted_proxies), Some(peer_addr)) => {
+                if let Some(client_ip) = trusted_proxies.client_ip(peer_addr.ip(), req.headers()) {
+                    attributes.push(HTTP_CLIENT_IP.string(client_ip.to_string()))
+                }
+            }
+            // without the peer address the forwarding headers cannot be trusted
+            (Some(_), None) => {}
+            (None, _) => {
+                if let Some(remote) = conn_info.realip_remote_addr() {
+                    attributes.push(HTTP_CLIENT_IP.string(remote.to_string()))
+                }
             }
         }
+        if let Some(peer_addr) = req.peer_addr() {
+            attributes.push(NET_PEER_IP.string(peer_addr.ip().to_string()));
+            attributes.push(NET_PEER_PORT.i64(peer_addr.port() as i64));
+        }
+        attributes.extend(self.captured_headers.request_attributes(req.headers()));
+        for hook in &self.hooks.on_request {
+            hook(&req, &mut attributes);
+        }
         builder.attributes = Some(attributes);
-        let span = self.tracer.build_with_context(builder, &parent_context);
-        let cx = parent_context.with_span(span);
+        let cx = self.tracer.0.start_with_context(builder, &parent_context);
         #[cfg(feature = "sync-middleware")]
         let attachment = cx.clone().attach();
         drop(conn_info);
+        let captured_headers = self.captured_headers.clone();
+        let hooks = self.hooks.clone();
+        let status_classifier = self.status_classifier.clone();
+        let trace_response = self.trace_response;
+        let cross_origin = req.headers().contains_key(header::ORIGIN);
 
         let fut = self
             .service
             .call(req)
             .with_context(cx.clone())
             .map(move |res| match res {
-                Ok(ok_res) => {
+                Ok(mut ok_res) => {
                     let span = cx.span();
                     span.set_attribute(HTTP_STATUS_CODE.i64(ok_res.status().as_u16() as i64));
-                    if ok_res.status().is_server_error() {
-                        span.set_status(
-                            StatusCode::Error,
-                            ok_res
-                                .status()
-                                .canonical_reason()
-                                .map(ToString::to_string)
-                                .unwrap_or_default(),
-                        );
+                    for attribute in captured_headers.response_attributes(ok_res.headers()) {
+                        span.set_attribute(attribute);
+                    }
+                    if let Some(err) = ok_res.response().error() {
+                        record_actix_exception(&span, err);
+                    }
+                    let status = ok_res.status();
+                    let description = match &status_classifier {
+                        Some(status_classifier) => {
+                            let error = ok_res.response().error().map(Error::as_response_error);
+                            status_classifier.classify(status, error)
+                        }
+                        None if status.is_server_error() => {
+                            Some(status.canonical_reason().unwrap_or_default().to_string())
+                        }
+                        None => None,
                     };
-                    span.end();
-                    Ok(ok_res)
+                    if let Some(description) = description {
+                        span.set_status(StatusCode::Error, description);
+                    };
+                    if let (Some(trace_response), Some(traceparent)) =
+                        (&trace_response, traceparent(span.span_context()))
+                    {
+                        trace_response.inject(&traceparent, &mut ok_res);
+                    }
+                    let ok_res = hooks.on_response(ok_res, &span);
+                    // ended once the body is sent
+                    Ok(ok_res.map_body(|_, body| RequestTracingBody::new(body, Some(cx))))
                 }
                 Err(err) => {
                     let span = cx.span();
-                    span.set_status(StatusCode::Error, format!("{:?}", err));
+                    record_actix_exception(&span, &err);
+                    let description = match &status_classifier {
+                        Some(status_classifier) => {
+                            let error = err.as_response_error();
+                            status_classifier.classify(error.status_code(), Some(error))
+                        }
+                        None => Some(format!("{:?}", err)),
+                    };
+                    if let Some(description) = description {
+                        span.set_status(StatusCode::Error, description);
+                    }
+                    let traceparent = traceparent(span.span_context());
                     span.end();
-                    Err(err)
+                    match (trace_response, traceparent) {
+                        (Some(trace_response), Some(traceparent)) => {
+                            Err(trace_response.inject_error(&traceparent, cross_origin, err))
+                        }
+                        _ => Err(err),
+                    }
                 }
             });
 
         #[cfg(feature = "sync-middleware")]
         drop(attachment);
-        Box::pin(async move { fut.await })
+        Box::pin(fut)
+    }
+}
+
+// The tracer of the server middleware, versioned like the crate.
+fn versioned_tracer<P: TracerProvider>(tracer_provider: &P) -> P::Tracer {
+    tracer_provider.versioned_tracer(
+        "actix-web-opentelemetry",
+        Some(env!("CARGO_PKG_VERSION")),
+        None,
+    )
+}
+
+/// Response body of requests traced by [`RequestTracing`], ending the span of the request
+/// once the body is sent.
+///
+/// The span ends at the end of the body, on a body error or when the body is dropped, e.g.
+/// when the client disconnects, and records the number of bytes sent as
+/// `http.response_content_length`.
+#[pin_project(PinnedDrop)]
+pub struct RequestTracingBody<B> {
+    #[pin]
+    body: B,
+    cx: Option<Context>,
+    sent: u64,
+}
+
+impl<B> RequestTracingBody<B> {
+    fn new(body: B, cx: Option<Context>) -> Self {
+        RequestTracingBody { body, cx, sent: 0 }
+    }
+}
+
+impl<B> fmt::Debug for RequestTracingBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestTracingBody")
+            .field("sent", &self.sent)
+            .finish()
+    }
+}
+
+fn end_body_span(cx: &mut Option<Context>, sent: u64, err: Option<&dyn StdError>) {
+    if let Some(cx) = cx.take() {
+        let span = cx.span();
+        span.set_attribute(HTTP_RESPONSE_CONTENT_LENGTH.i64(sent as i64));
+        if let Some(err) = err {
+            record_exception(&span, None, err);
+            span.set_status(StatusCode::Error, err.to_string());
+        }
+        span.end();
+    }
+}
+
+impl<B: MessageBody> MessageBody for RequestTracingBody<B> {
+    type Error = Box<dyn StdError>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.project();
+        // in the context of the span for spans created while streaming the body
+        let _guard = this.cx.clone().map(Context::attach);
+        match ready!(this.body.poll_next(cx)) {
+            Some(Ok(chunk)) => {
+                *this.sent += chunk.len() as u64;
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(err)) => {
+                let err = err.into();
+                end_body_span(this.cx, *this.sent, Some(err.as_ref()));
+                Poll::Ready(Some(Err(err)))
+            }
+            None => {
+                end_body_span(this.cx, *this.sent, None);
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for RequestTracingBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        end_body_span(this.cx, *this.sent, None);
     }
 }
 
@@ -278,3 +778,275 @@ impl<'a> Extractor for RequestHeaderCarrier<'a> {
         self.headers.keys().map(|header| header.as_str()).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        dev::fn_service,
+        error::{ErrorBadRequest, ErrorInternalServerError},
+        test, web, App,
+    };
+    use actix_web_telemetry_common::test_util::SpanCollector;
+    use opentelemetry::Key;
+    use opentelemetry_semantic_conventions::trace::{EXCEPTION_STACKTRACE, EXCEPTION_TYPE};
+
+    #[actix_web::test]
+    async fn traces_with_tracer_provider() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let app = test::init_service(
+            App::new()
+                .wrap(RequestTracing::new().with_tracer_provider(&provider))
+                .route("/users/{id}", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/users/1").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(body, "ok");
+
+        let spans = collector.spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "/users/{id}");
+        assert_eq!(spans[0].span_kind, SpanKind::Server);
+        assert_eq!(spans[0].instrumentation_lib.name, "actix-web-opentelemetry");
+    }
+
+    #[actix_web::test]
+    async fn names_spans_with_closures() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let tracing = RequestTracing::new()
+            .with_tracer_provider(&provider)
+            .with_span_namer(|req: &ServiceRequest, route: Option<&str>| {
+                format!("{} {}", req.method(), route.unwrap_or("unmatched"))
+            });
+        let app = test::init_service(
+            App::new()
+                .wrap(tracing)
+                .route("/users/{id}", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/users/1").to_request();
+        test::call_and_read_body(&app, req).await;
+
+        let spans = collector.spans();
+        assert_eq!(spans[0].name, "GET /users/{id}");
+    }
+
+    #[actix_web::test]
+    async fn classifies_errors_of_the_service() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let service = || {
+            fn_service(|req: ServiceRequest| async move {
+                Err::<ServiceResponse, _>(match req.path() {
+                    "/invalid" => ErrorBadRequest("invalid id"),
+                    _ => ErrorInternalServerError("database unavailable"),
+                })
+            })
+        };
+        let tracing = || RequestTracing::new().with_tracer_provider(&provider);
+        let classified = tracing().with_status_classifier(crate::DefaultStatusClassifier);
+        for tracing in [tracing(), classified] {
+            let middleware = tracing.new_transform(service()).await.unwrap();
+            for path in ["/invalid", "/users"] {
+                let req = test::TestRequest::get().uri(path).to_srv_request();
+                assert!(middleware.call(req).await.is_err());
+            }
+        }
+
+        let spans = collector.spans();
+        let statuses: Vec<_> = spans
+            .iter()
+            .map(|span| (span.status_code, span.status_message.as_ref()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                // every error of the service by default, with its `Debug` representation
+                (StatusCode::Error, "\"invalid id\""),
+                (StatusCode::Error, "\"database unavailable\""),
+                // client errors are not errors for the default classifier
+                (StatusCode::Unset, ""),
+                (StatusCode::Error, "database unavailable"),
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn records_causes_of_actix_errors() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let tracing = RequestTracing::new().with_tracer_provider(&provider);
+        let service = fn_service(|_req: ServiceRequest| async move {
+            let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset by peer");
+            Err::<ServiceResponse, _>(actix_web::error::PayloadError::Io(reset).into())
+        });
+        let middleware = tracing.new_transform(service).await.unwrap();
+
+        let req = test::TestRequest::get().uri("/").to_srv_request();
+        assert!(middleware.call(req).await.is_err());
+
+        let spans = collector.spans();
+        let event = spans[0].events.iter().next().unwrap();
+        assert_eq!(event.name, "exception");
+        let attribute = |key: Key| {
+            event
+                .attributes
+                .iter()
+                .find(|kv| kv.key == key)
+                .map(|kv| kv.value.to_string())
+        };
+        assert_eq!(
+            attribute(EXCEPTION_TYPE).as_deref(),
+            Some("actix_http::error::PayloadError")
+        );
+        assert_eq!(
+            attribute(EXCEPTION_STACKTRACE).as_deref(),
+            Some("reset by peer\n\nCaused by:\n    0: reset by peer")
+        );
+    }
+
+    #[actix_web::test]
+    async fn trusts_forwarded_client_ip_of_trusted_peers_only() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let tracing = RequestTracing::new()
+            .with_tracer_provider(&provider)
+            .with_trusted_proxies(TrustedProxies::new().trust_private_networks());
+        let middleware = tracing.new_transform(test::ok_service()).await.unwrap();
+
+        let requests = [
+            (Some("10.0.0.1:4711"), "203.0.113.1"),
+            (Some("198.51.100.7:4711"), "203.0.113.1"),
+            (None, "203.0.113.1"),
+            (Some("10.0.0.1:4711"), "unknown"),
+        ];
+        for (peer, forwarded_for) in requests {
+            let mut req = test::TestRequest::get()
+                .uri("/")
+                .insert_header((header::X_FORWARDED_FOR, forwarded_for));
+            if let Some(peer) = peer {
+                req = req.peer_addr(peer.parse().unwrap());
+            }
+            middleware.call(req.to_srv_request()).await.unwrap();
+        }
+
+        let spans = collector.spans();
+        let client_ips: Vec<_> = spans
+            .iter()
+            .map(|span| {
+                span.attributes
+                    .get(&HTTP_CLIENT_IP)
+                    .map(|ip| ip.as_str().into_owned())
+            })
+            .collect();
+        assert_eq!(
+            client_ips,
+            [
+                Some("203.0.113.1".to_string()),
+                Some("198.51.100.7".to_string()),
+                // the forwarding headers are not trusted without a peer
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn extends_filter_of_built_middleware() {
+        let tracing = RequestTracing::new().exclude_path("/healthz");
+        let middleware = tracing.new_transform(test::ok_service()).await.unwrap();
+
+        let tracing = tracing.exclude_path("/metrics");
+        assert_eq!(tracing.request_filter.paths, ["/healthz", "/metrics"]);
+        assert_eq!(middleware.request_filter.paths, ["/healthz"]);
+    }
+
+    #[actix_web::test]
+    async fn adds_hooks_to_built_middleware() {
+        let tracing = RequestTracing::new().on_request(|_, _| {});
+        let middleware = tracing.new_transform(test::ok_service()).await.unwrap();
+
+        let tracing = tracing.on_request(|_, _| {}).on_response(|_, _| {});
+        assert_eq!(tracing.hooks.on_request.len(), 2);
+        assert_eq!(tracing.hooks.on_response.len(), 1);
+        assert_eq!(middleware.hooks.on_request.len(), 1);
+        assert!(middleware.hooks.on_response.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn injects_trace_response() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    RequestTracing::new()
+                        .with_tracer_provider(&provider)
+                        .with_trace_response(TraceResponse::new().server_timing(true)),
+                )
+                .route("/", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://example.com"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let headers = res.headers().clone();
+        test::read_body(res).await;
+
+        let spans = collector.spans();
+        let traceparent = format!(
+            "00-{:032x}-{:016x}-01",
+            spans[0].span_context.trace_id(),
+            spans[0].span_context.span_id()
+        );
+        assert_eq!(headers.get("traceresponse").unwrap(), traceparent.as_str());
+        assert_eq!(
+            headers.get("server-timing").unwrap(),
+            format!("traceparent;desc=\"{}\"", traceparent).as_str()
+        );
+        let exposed: Vec<_> = headers
+            .get_all(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .collect();
+        assert_eq!(exposed, ["traceresponse", "server-timing"]);
+    }
+
+    #[actix_web::test]
+    async fn injects_trace_response_into_errors() {
+        let collector = SpanCollector::default();
+        let provider = collector.provider();
+        let tracing = RequestTracing::new()
+            .with_tracer_provider(&provider)
+            .with_trace_response(TraceResponse::new());
+        let service = fn_service(|_req: ServiceRequest| async move {
+            Err::<ServiceResponse, _>(ErrorInternalServerError("database unavailable"))
+        });
+        let middleware = tracing.new_transform(service).await.unwrap();
+
+        let req = test::TestRequest::get().uri("/").to_srv_request();
+        let err = middleware.call(req).await.unwrap_err();
+        let res = err.error_response();
+        assert_eq!(
+            res.status(),
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+        );
+
+        let spans = collector.spans();
+        let traceparent = format!(
+            "00-{:032x}-{:016x}-01",
+            spans[0].span_context.trace_id(),
+            spans[0].span_context.span_id()
+        );
+        assert_eq!(
+            res.headers().get("traceresponse").unwrap(),
+            traceparent.as_str()
+        );
+    }
+}
@@ -1,5 +1,16 @@
-use actix_web::http::{Method, Version};
-use std::borrow::Cow;
+use actix_web::{
+    error,
+    http::{Method, Version},
+};
+use actix_web_telemetry_common::exception_stacktrace;
+use opentelemetry::{
+    trace::{SpanBuilder, SpanContext, SpanRef, TraceContextExt, Tracer},
+    Context,
+};
+use opentelemetry_semantic_conventions::trace::{
+    EXCEPTION_MESSAGE, EXCEPTION_STACKTRACE, EXCEPTION_TYPE,
+};
+use std::{borrow::Cow, error::Error as StdError, fmt, sync::Arc};
 
 #[inline]
 pub(super) fn http_method_str(method: &Method) -> Cow<'static, str> {
@@ -37,3 +48,98 @@ pub(super) fn http_scheme(scheme: &str) -> Cow<'static, str> {
         other => other.to_string().into(),
     }
 }
+
+/// The W3C `traceparent` representation of `span_context`, if valid.
+pub(super) fn traceparent(span_context: &SpanContext) -> Option<String> {
+    if !span_context.is_valid() {
+        return None;
+    }
+    let flags = if span_context.is_sampled() {
+        "01"
+    } else {
+        "00"
+    };
+    Some(format!(
+        "00-{:032x}-{:016x}-{}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        flags
+    ))
+}
+
+// Object safe `Tracer` starting spans in a context.
+pub(super) trait ContextTracer {
+    fn start_with_context(&self, builder: SpanBuilder, cx: &Context) -> Context;
+}
+
+impl<T> ContextTracer for T
+where
+    T: Tracer,
+    T::Span: Send + Sync + 'static,
+{
+    fn start_with_context(&self, builder: SpanBuilder, cx: &Context) -> Context {
+        cx.with_span(builder.start_with_context(self, cx))
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SharedTracer(pub(super) Arc<dyn ContextTracer + Send + Sync>);
+
+impl fmt::Debug for SharedTracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedTracer")
+    }
+}
+
+/// Record `err` as an `exception` event on `span`, with its chain of sources as
+/// `exception.stacktrace`.
+pub(super) fn record_exception(
+    span: &SpanRef<'_>,
+    exception_type: Option<&'static str>,
+    err: &dyn StdError,
+) {
+    let mut attributes = Vec::with_capacity(3);
+    if let Some(exception_type) = exception_type {
+        attributes.push(EXCEPTION_TYPE.string(exception_type));
+    }
+    attributes.push(EXCEPTION_MESSAGE.string(err.to_string()));
+    attributes.push(EXCEPTION_STACKTRACE.string(exception_stacktrace(err)));
+    span.add_event("exception", attributes);
+}
+
+/// Record the actix-web error `err` as an `exception` event on `span`, with the type and
+/// causes of the error it wraps if that is a boxed [`std::error::Error`] or one of the
+/// common actix-web, I/O and serialization errors.
+pub(super) fn record_actix_exception(span: &SpanRef<'_>, err: &actix_web::Error) {
+    // `actix_web::Error` has no source, its inner error is only reachable by downcasting
+    macro_rules! record_inner {
+        ($($error:ty),* $(,)?) => {
+            $(
+                if let Some(inner) = err.as_error::<$error>() {
+                    return record_exception(span, Some(std::any::type_name::<$error>()), inner);
+                }
+            )*
+        };
+    }
+
+    if let Some(inner) = err.as_error::<Box<dyn StdError>>() {
+        return record_exception(span, None, inner.as_ref());
+    }
+    record_inner!(
+        error::PayloadError,
+        error::JsonPayloadError,
+        error::UrlencodedError,
+        error::QueryPayloadError,
+        error::PathError,
+        error::ReadlinesError,
+        error::ContentTypeError,
+        error::ParseError,
+        error::HttpError,
+        error::BlockingError,
+        error::UrlGenerationError,
+        std::io::Error,
+        std::str::Utf8Error,
+        serde_json::Error,
+    );
+    record_exception(span, Some(std::any::type_name::<actix_web::Error>()), err)
+}
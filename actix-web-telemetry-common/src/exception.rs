@@ -0,0 +1,43 @@
+use std::error::Error as StdError;
+
+/// Format `err` as an OpenTelemetry `exception.stacktrace`.
+///
+/// Rust errors carry no stack trace, so this lists the chain of
+/// [`source`](StdError::source)s of the error, or returns its `Debug` representation if it
+/// has none.
+pub fn exception_stacktrace(err: &dyn StdError) -> String {
+    let mut source = err.source();
+    if source.is_none() {
+        return format!("{:?}", err);
+    }
+
+    let mut stacktrace = format!("{}\n\nCaused by:", err);
+    let mut depth = 0;
+    while let Some(cause) = source {
+        stacktrace.push_str(&format!("\n    {}: {}", depth, cause));
+        source = cause.source();
+        depth += 1;
+    }
+    stacktrace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn lists_sources() {
+        let err = io::Error::new(io::ErrorKind::ConnectionReset, "request failed");
+        assert_eq!(
+            exception_stacktrace(&err),
+            r#"Custom { kind: ConnectionReset, error: "request failed" }"#
+        );
+
+        let err = actix_web::error::PayloadError::Io(err);
+        assert_eq!(
+            exception_stacktrace(&err),
+            "request failed\n\nCaused by:\n    0: request failed"
+        );
+    }
+}
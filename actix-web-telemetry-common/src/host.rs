@@ -0,0 +1,36 @@
+/// Split a `Host` like `example.com:8080` or `[::1]:8080` into its name and port, without
+/// the brackets of IPv6 literals.
+///
+/// ```rust
+/// use actix_web_telemetry_common::split_host;
+///
+/// assert_eq!(split_host("[::1]:8080"), ("::1", Some(8080)));
+/// ```
+pub fn split_host(host: &str) -> (&str, Option<u16>) {
+    if let Some(literal) = host.strip_prefix('[') {
+        return match literal.split_once(']') {
+            Some((name, port)) => (name, port.strip_prefix(':').and_then(|p| p.parse().ok())),
+            None => (host, None),
+        };
+    }
+    match host.rsplit_once(':') {
+        // a colon in the name is an IPv6 literal without brackets and port
+        Some((name, port)) if !name.contains(':') => (name, port.parse().ok()),
+        _ => (host, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(split_host("example.com"), ("example.com", None));
+        assert_eq!(split_host("example.com:8080"), ("example.com", Some(8080)));
+        assert_eq!(split_host("127.0.0.1:80"), ("127.0.0.1", Some(80)));
+        assert_eq!(split_host("[::1]:8080"), ("::1", Some(8080)));
+        assert_eq!(split_host("[::1]"), ("::1", None));
+        assert_eq!(split_host("::1"), ("::1", None));
+    }
+}
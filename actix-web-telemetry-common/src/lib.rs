@@ -0,0 +1,15 @@
+//! Building blocks shared by the `actix-web-opentelemetry` and `tracing-actix-web`
+//! middlewares, re-exported by both.
+mod exception;
+mod host;
+mod status_classifier;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod trace_response;
+mod trusted_proxies;
+
+pub use exception::exception_stacktrace;
+pub use host::split_host;
+pub use status_classifier::{DefaultStatusClassifier, StatusClassifier};
+pub use trace_response::TraceResponse;
+pub use trusted_proxies::TrustedProxies;
@@ -0,0 +1,93 @@
+//! # Status Classifier
+//!
+//! Decide which requests are recorded as errors.
+use actix_web::{http::StatusCode, ResponseError};
+
+/// Interface for deciding whether the span of a request is recorded as an error.
+///
+/// The classifier is given the status code of the response and, if the request failed with
+/// an error, the error itself. Returning `Some(description)` sets the span status to
+/// `Error` with the given description, returning `None` does not.
+///
+/// Classifiers are opt-in, the middlewares keep their own defaults without one.
+/// [`DefaultStatusClassifier`] records server errors (5xx) and requests that failed with an
+/// error as errors, unless the status is a client error (4xx).
+///
+/// # Custom Status Classifier Examples
+///
+/// ```
+/// use actix_web::{http::StatusCode, ResponseError};
+/// use actix_web_telemetry_common::{DefaultStatusClassifier, StatusClassifier};
+///
+/// // Rate limited and conflicting requests are errors, load-shedding is not.
+/// #[derive(Debug)]
+/// struct StrictClassifier;
+///
+/// impl StatusClassifier for StrictClassifier {
+///     fn classify(&self, status: StatusCode, error: Option<&dyn ResponseError>) -> Option<String> {
+///         match status {
+///             StatusCode::TOO_MANY_REQUESTS | StatusCode::CONFLICT => {
+///                 Some(status.canonical_reason().unwrap_or_default().to_string())
+///             }
+///             StatusCode::SERVICE_UNAVAILABLE => None,
+///             _ => DefaultStatusClassifier.classify(status, error),
+///         }
+///     }
+/// }
+/// ```
+pub trait StatusClassifier: std::fmt::Debug {
+    /// Return the error description of a request with the given `status` and `error`, or
+    /// `None` if it is not an error.
+    fn classify(&self, status: StatusCode, error: Option<&dyn ResponseError>) -> Option<String>;
+}
+
+/// Records server errors (5xx) and requests that failed with an error as errors, unless the
+/// status is a client error (4xx).
+///
+/// The description is the `Display` representation of the error if there was one, the
+/// reason phrase of the status code otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultStatusClassifier;
+
+impl StatusClassifier for DefaultStatusClassifier {
+    fn classify(&self, status: StatusCode, error: Option<&dyn ResponseError>) -> Option<String> {
+        match error {
+            Some(_) if status.is_client_error() => None,
+            Some(error) => Some(error.to_string()),
+            None if status.is_server_error() => {
+                Some(status.canonical_reason().unwrap_or_default().to_string())
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+
+    #[test]
+    fn classifies_server_errors_and_failed_requests() {
+        let classifier = DefaultStatusClassifier;
+        assert_eq!(classifier.classify(StatusCode::OK, None), None);
+        assert_eq!(classifier.classify(StatusCode::NOT_FOUND, None), None);
+        assert_eq!(
+            classifier.classify(StatusCode::BAD_GATEWAY, None).as_deref(),
+            Some("Bad Gateway")
+        );
+
+        let bad_request = ErrorBadRequest("invalid id");
+        let error = bad_request.as_response_error();
+        assert_eq!(classifier.classify(StatusCode::BAD_REQUEST, Some(error)), None);
+
+        let internal = ErrorInternalServerError("database unavailable");
+        let error = internal.as_response_error();
+        assert_eq!(
+            classifier
+                .classify(StatusCode::INTERNAL_SERVER_ERROR, Some(error))
+                .as_deref(),
+            Some("database unavailable")
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Helpers for the tests of the middlewares, enabled by the `test-util` feature.
+use opentelemetry::sdk::export::trace::SpanData;
+use opentelemetry::sdk::trace::{Span, SpanProcessor, TracerProvider};
+use opentelemetry::trace::TraceResult;
+use opentelemetry::Context;
+use std::sync::{Arc, Mutex};
+
+/// Collects the spans ended by a [`TracerProvider`].
+///
+/// ```
+/// use actix_web_telemetry_common::test_util::SpanCollector;
+/// use opentelemetry::trace::{Tracer, TracerProvider};
+///
+/// let collector = SpanCollector::default();
+/// collector.provider().tracer("test").in_span("work", |_cx| {});
+/// assert_eq!(collector.spans()[0].name, "work");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SpanCollector(Arc<Mutex<Vec<SpanData>>>);
+
+impl SpanCollector {
+    /// A tracer provider whose spans are collected by `self`.
+    pub fn provider(&self) -> TracerProvider {
+        TracerProvider::builder()
+            .with_span_processor(self.clone())
+            .build()
+    }
+
+    /// The spans ended so far, in the order they ended.
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl SpanProcessor for SpanCollector {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        self.0.lock().unwrap().push(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        Ok(())
+    }
+}
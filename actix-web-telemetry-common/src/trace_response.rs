@@ -0,0 +1,188 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::Error;
+
+const TRACERESPONSE: &str = "traceresponse";
+const SERVER_TIMING: &str = "server-timing";
+
+/// Headers injecting the trace context of server spans into responses, e.g. to link browser
+/// requests to the backend trace.
+///
+/// By default the context is injected as a W3C [`traceresponse`] header, e.g.
+/// `traceresponse: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+/// It can also be added as a `traceparent` metric to the `Server-Timing` header, e.g.
+/// `Server-Timing: traceparent;desc="00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"`,
+/// which browsers expose through the Performance API.
+///
+/// Responses to cross-origin requests also list the injected headers in
+/// `Access-Control-Expose-Headers` so that they are readable by scripts. CORS middleware
+/// replacing this header, like `actix-cors`, should either expose the headers itself or be
+/// registered before the tracing middleware. Cross-origin `Server-Timing` entries
+/// additionally require the `Timing-Allow-Origin` header, which is left to the application.
+///
+/// # Errors
+///
+/// An error returned by the wrapped service instead of a response has no headers to inject
+/// into, so the middlewares replace it with an [`InternalError`] carrying its error response
+/// with the injected headers. Outer middleware, including [`ErrorHandlers`], then see the
+/// `InternalError` rather than the original error: `as_error::<T>()` returns `None` for its
+/// type. Middleware downcasting errors should be registered before the tracing middleware.
+///
+/// ```rust
+/// use actix_web_telemetry_common::TraceResponse;
+///
+/// let trace_response = TraceResponse::new().server_timing(true);
+/// ```
+///
+/// [`traceresponse`]: https://w3c.github.io/trace-context/#traceresponse-header
+/// [`ErrorHandlers`]: actix_web::middleware::ErrorHandlers
+#[derive(Clone, Copy, Debug)]
+pub struct TraceResponse {
+    traceresponse: bool,
+    server_timing: bool,
+    expose_headers: bool,
+}
+
+impl Default for TraceResponse {
+    fn default() -> Self {
+        TraceResponse {
+            traceresponse: true,
+            server_timing: false,
+            expose_headers: true,
+        }
+    }
+}
+
+impl TraceResponse {
+    /// Inject the `traceresponse` header and expose it to cross-origin requests.
+    pub fn new() -> Self {
+        TraceResponse::default()
+    }
+
+    /// Inject the `traceresponse` header, enabled by default.
+    pub fn traceresponse(mut self, enabled: bool) -> Self {
+        self.traceresponse = enabled;
+        self
+    }
+
+    /// Add a `traceparent` metric to the `Server-Timing` header, disabled by default.
+    pub fn server_timing(mut self, enabled: bool) -> Self {
+        self.server_timing = enabled;
+        self
+    }
+
+    /// List the injected headers in `Access-Control-Expose-Headers` of responses to
+    /// cross-origin requests, enabled by default.
+    pub fn expose_headers(mut self, enabled: bool) -> Self {
+        self.expose_headers = enabled;
+        self
+    }
+
+    /// Inject the W3C `traceparent` representation of the server span, e.g.
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`, into `response`.
+    pub fn inject<B>(&self, traceparent: &str, response: &mut ServiceResponse<B>) {
+        let cross_origin = response.request().headers().contains_key(header::ORIGIN);
+        self.inject_headers(traceparent, cross_origin, response.headers_mut());
+    }
+
+    /// Inject the W3C `traceparent` representation of the server span into the error
+    /// response of `err`, returned instead of a response to a request that was
+    /// `cross_origin`, i.e. had an `Origin` header.
+    ///
+    /// The returned error wraps `err` in an [`InternalError`] carrying the error response, see
+    /// [Errors](TraceResponse#errors).
+    pub fn inject_error(&self, traceparent: &str, cross_origin: bool, err: Error) -> Error {
+        let mut response = err.error_response();
+        self.inject_headers(traceparent, cross_origin, response.headers_mut());
+        InternalError::from_response(err, response).into()
+    }
+
+    fn inject_headers(&self, traceparent: &str, cross_origin: bool, headers: &mut HeaderMap) {
+        let cross_origin = self.expose_headers && cross_origin;
+        if self.traceresponse {
+            if let Ok(value) = HeaderValue::from_str(traceparent) {
+                headers.insert(HeaderName::from_static(TRACERESPONSE), value);
+                if cross_origin {
+                    expose(headers, TRACERESPONSE);
+                }
+            }
+        }
+        if self.server_timing {
+            let metric = format!("traceparent;desc=\"{}\"", traceparent);
+            if let Ok(value) = HeaderValue::from_str(&metric) {
+                headers.append(HeaderName::from_static(SERVER_TIMING), value);
+                if cross_origin {
+                    expose(headers, SERVER_TIMING);
+                }
+            }
+        }
+    }
+}
+
+// Add `name` to the exposed headers unless it is already exposed.
+fn expose(headers: &mut HeaderMap, name: &'static str) {
+    let exposed = headers
+        .get_all(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|exposed| exposed == "*" || exposed.eq_ignore_ascii_case(name));
+    if !exposed {
+        headers.append(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(name),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::ErrorServiceUnavailable;
+    use actix_web::test::TestRequest;
+    use actix_web::HttpResponse;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn exposes_injected_headers_once() {
+        let req = TestRequest::default()
+            .insert_header((header::ORIGIN, "https://example.com"))
+            .to_http_request();
+        let res = HttpResponse::Ok()
+            .insert_header((header::ACCESS_CONTROL_EXPOSE_HEADERS, "traceresponse"))
+            .finish();
+        let mut res = ServiceResponse::new(req, res);
+
+        TraceResponse::new()
+            .server_timing(true)
+            .inject(TRACEPARENT, &mut res);
+
+        let headers = res.headers();
+        assert_eq!(headers.get(TRACERESPONSE).unwrap(), TRACEPARENT);
+        assert_eq!(
+            headers.get(SERVER_TIMING).unwrap(),
+            "traceparent;desc=\"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\""
+        );
+        let exposed: Vec<_> = headers
+            .get_all(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+            .collect();
+        assert_eq!(exposed, ["traceresponse", "server-timing"]);
+    }
+
+    #[test]
+    fn injects_headers_into_error_responses() {
+        let err = ErrorServiceUnavailable("database unavailable");
+
+        let err = TraceResponse::new().inject_error(TRACEPARENT, false, err);
+
+        let res = err.error_response();
+        assert_eq!(res.status(), 503);
+        assert_eq!(res.headers().get(TRACERESPONSE).unwrap(), TRACEPARENT);
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_EXPOSE_HEADERS));
+        assert_eq!(err.to_string(), "database unavailable");
+    }
+}
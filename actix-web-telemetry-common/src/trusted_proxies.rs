@@ -0,0 +1,231 @@
+use actix_web::http::header::{self, HeaderMap, HeaderName};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// Headers read when none are configured, in order of preference.
+const DEFAULT_HEADERS: [HeaderName; 2] = [header::FORWARDED, header::X_FORWARDED_FOR];
+
+/// Proxies trusted to report the client IP of requests, recorded as `http.client_ip`.
+///
+/// By default, the tracing middlewares record the client IP reported in the `Forwarded` or
+/// `X-Forwarded-For` headers by any peer, which clients can spoof. With trusted proxies the
+/// client IP is the peer address, unless the peer is a trusted proxy. The addresses of the
+/// forwarding header are then walked from the closest hop, skipping trusted proxies, and the
+/// first untrusted address is the client IP. When that hop is `unknown`, obfuscated or cannot
+/// be parsed, or without a peer address, e.g. on Unix sockets, no client IP is recorded.
+///
+/// ```rust
+/// use actix_web::http::header;
+/// use actix_web_telemetry_common::TrustedProxies;
+/// use std::net::Ipv4Addr;
+///
+/// // behind load balancers in 10.0.0.0/8 setting `X-Forwarded-For`
+/// let trusted_proxies = TrustedProxies::new()
+///     .trust(Ipv4Addr::new(10, 0, 0, 0), 8)
+///     .header(header::X_FORWARDED_FOR);
+///
+/// let headers = header::HeaderMap::new();
+/// let peer = "198.51.100.7".parse().unwrap();
+/// assert_eq!(trusted_proxies.client_ip(peer, &headers), Some(peer));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+    headers: Vec<HeaderName>,
+}
+
+impl TrustedProxies {
+    /// Trust no proxy, recording the peer address as the client IP.
+    pub fn new() -> Self {
+        TrustedProxies::default()
+    }
+
+    /// Trust proxies in the network `addr/prefix_len`, e.g. `10.0.0.0/8`.
+    ///
+    /// # Panics
+    ///
+    /// If `prefix_len` is longer than `addr`, i.e. 32 for IPv4 and 128 for IPv6 addresses.
+    pub fn trust(mut self, addr: impl Into<IpAddr>, prefix_len: u8) -> Self {
+        let addr = addr.into();
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        assert!(
+            prefix_len <= max_len,
+            "prefix length {} is longer than {}",
+            prefix_len,
+            max_len
+        );
+        self.networks.push((addr, prefix_len));
+        self
+    }
+
+    /// Trust proxies on loopback and private networks: `127.0.0.0/8`, `10.0.0.0/8`,
+    /// `172.16.0.0/12`, `192.168.0.0/16`, `::1/128` and `fc00::/7`.
+    pub fn trust_private_networks(self) -> Self {
+        self.trust(Ipv4Addr::new(127, 0, 0, 0), 8)
+            .trust(Ipv4Addr::new(10, 0, 0, 0), 8)
+            .trust(Ipv4Addr::new(172, 16, 0, 0), 12)
+            .trust(Ipv4Addr::new(192, 168, 0, 0), 16)
+            .trust(Ipv6Addr::LOCALHOST, 128)
+            .trust(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7)
+    }
+
+    /// Read the client IP from the header `name`, in order of preference.
+    ///
+    /// `Forwarded` headers are read from their `for` parameters, other headers as a comma
+    /// separated list of addresses like `X-Forwarded-For`. Defaults to `Forwarded`, then
+    /// `X-Forwarded-For`.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// The IP of the client of a request with the given `headers`, received from `peer`, or
+    /// `None` if a proxy forwarded it from an unknown address.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = canonical(peer);
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let names = if self.headers.is_empty() {
+            &DEFAULT_HEADERS[..]
+        } else {
+            &self.headers[..]
+        };
+        for name in names {
+            let hops = forwarded_for(headers, name);
+            if hops.is_empty() {
+                continue;
+            }
+
+            let mut client = peer;
+            for hop in hops.into_iter().rev() {
+                match hop {
+                    Some(hop) if self.is_trusted(hop) => client = hop,
+                    Some(hop) => return Some(hop),
+                    // unknown or obfuscated hop, the trusted hops behind it are not the client
+                    None => return None,
+                }
+            }
+            return Some(client);
+        }
+
+        Some(peer)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks
+            .iter()
+            .any(|&(network, prefix_len)| contains(network, prefix_len, ip))
+    }
+}
+
+fn contains(network: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+// IPv4 peers of dual stack sockets are reported as IPv4-mapped IPv6 addresses.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+// The addresses of all hops listed in the header `name`, from the client to the closest hop.
+fn forwarded_for(headers: &HeaderMap, name: &HeaderName) -> Vec<Option<IpAddr>> {
+    let values = headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    if name == header::FORWARDED {
+        values
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.trim().split_once('=')?;
+                    key.eq_ignore_ascii_case("for").then(|| parse_node(value))
+                })
+            })
+            .collect()
+    } else {
+        values.map(parse_node).collect()
+    }
+}
+
+// Parse `192.0.2.43`, `"[2001:db8::1]:4711"` and the like, `None` for e.g. `unknown`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok().map(canonical);
+    }
+    node.parse()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+
+    fn headers(name: HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn ignores_headers_of_untrusted_peers() {
+        let proxies = TrustedProxies::new().trust_private_networks();
+        let headers = headers(header::X_FORWARDED_FOR, "203.0.113.1");
+        let peer = "198.51.100.7".parse().unwrap();
+        assert_eq!(proxies.client_ip(peer, &headers), Some(peer));
+    }
+
+    #[test]
+    fn skips_trusted_hops() {
+        let proxies = TrustedProxies::new().trust(Ipv4Addr::new(10, 0, 0, 0), 8);
+        let headers = headers(header::X_FORWARDED_FOR, "192.0.2.1, 203.0.113.1, 10.1.2.3");
+        let peer = "::ffff:10.0.0.1".parse().unwrap();
+        assert_eq!(
+            proxies.client_ip(peer, &headers),
+            "203.0.113.1".parse::<IpAddr>().ok()
+        );
+    }
+
+    #[test]
+    fn records_no_client_ip_behind_unknown_hops() {
+        let proxies = TrustedProxies::new().trust(Ipv4Addr::new(10, 0, 0, 0), 8);
+        let peer = "10.0.0.1".parse().unwrap();
+        for value in ["192.0.2.1, unknown, 10.1.2.3", "203.0.113.1, garbage"] {
+            let headers = headers(header::X_FORWARDED_FOR, value);
+            assert_eq!(proxies.client_ip(peer, &headers), None, "{}", value);
+        }
+
+        let headers = headers(header::FORWARDED, "for=_hidden, for=10.1.2.3");
+        assert_eq!(proxies.client_ip(peer, &headers), None);
+    }
+
+    #[test]
+    fn reads_forwarded_for_parameters() {
+        let proxies = TrustedProxies::new().trust(Ipv6Addr::LOCALHOST, 128);
+        let headers = headers(
+            header::FORWARDED,
+            "for=192.0.2.60;proto=http, For=\"[2001:db8:cafe::17]:4711\"",
+        );
+        assert_eq!(
+            proxies.client_ip(Ipv6Addr::LOCALHOST.into(), &headers),
+            "2001:db8:cafe::17".parse::<IpAddr>().ok()
+        );
+    }
+}
@@ -1,8 +1,28 @@
-use awc::ClientRequest;
-use tracing::Span;
+use actix_http::{encoding::Decoder, BoxedPayloadStream, Payload};
+use actix_web::body::MessageBody;
+use actix_web::web::Bytes;
+use actix_web_opentelemetry::{ActixClientCarrier, RouteFormatter};
+use actix_web_telemetry_common::exception_stacktrace;
+use awc::error::{HttpError, SendRequestError};
+use awc::http::header::{HeaderMap, HeaderValue, TryIntoHeaderPair};
+use awc::http::{Method, Uri, Version};
+use awc::{Client, ClientRequest, ClientResponse};
+use futures_util::{Future, Stream};
+use opentelemetry::global;
+use serde::Serialize;
+use std::fmt::{self, Write as _};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::field::{display, Empty};
+use tracing::{Instrument, Span};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-pub use actix_web_opentelemetry::{ClientExt, InstrumentedClientRequest};
+pub use actix_web_opentelemetry::{ClientExt, InstrumentedClientRequest, UrlRedactor};
+pub use retry::{RetryClientRequest, RetryPolicy};
+
+mod retry;
+
+type AwcResult = Result<ClientResponse<Decoder<Payload<BoxedPayloadStream>>>, SendRequestError>;
 
 pub trait TracingClientExt {
     fn with_span(self, span: Span) -> InstrumentedClientRequest;
@@ -13,10 +33,463 @@ pub trait TracingClientExt {
     {
         self.with_span(Span::current())
     }
+
+    /// Record the request as a `tracing` span, a child of the current span, so it shows up
+    /// in logs as well as in traces.
+    fn client_span(self) -> SpannedClientRequest;
+
+    /// Send the request with retries, as one logical span with a child span per attempt.
+    fn with_retry(self, policy: RetryPolicy) -> RetryClientRequest;
 }
 
 impl TracingClientExt for ClientRequest {
     fn with_span(self, span: Span) -> InstrumentedClientRequest {
         self.trace_request_with_context(span.context())
     }
+
+    fn client_span(self) -> SpannedClientRequest {
+        SpannedClientRequest::new(self, UrlRedactor::default())
+    }
+
+    fn with_retry(self, policy: RetryPolicy) -> RetryClientRequest {
+        RetryClientRequest::new(self, policy, UrlRedactor::default())
+    }
+}
+
+/// How [`TracedClient`] records client spans.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpanMode {
+    /// Start the span on the global OpenTelemetry tracer, see [`InstrumentedClientRequest`].
+    #[default]
+    OpenTelemetry,
+    /// Record the span through `tracing`, see [`SpannedClientRequest`].
+    Tracing,
+}
+
+/// An [`awc::Client`] whose requests are all traced with the [`Span`] current at send time.
+#[derive(Clone, Default)]
+pub struct TracedClient {
+    client: Client,
+    mode: SpanMode,
+    url_redactor: UrlRedactor,
+}
+
+impl TracedClient {
+    pub fn new() -> Self {
+        TracedClient::default()
+    }
+
+    pub fn with_span_mode(mut self, mode: SpanMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Redact the `http.url` of the spans, by default only credentials are removed.
+    pub fn with_url_redactor(mut self, url_redactor: UrlRedactor) -> Self {
+        self.url_redactor = url_redactor;
+        self
+    }
+
+    pub fn request<U>(&self, method: Method, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        TracedClientRequest {
+            request: self.client.request(method, url),
+            mode: self.mode,
+            url_redactor: self.url_redactor.clone(),
+        }
+    }
+
+    pub fn get<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::GET, url)
+    }
+
+    pub fn head<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::HEAD, url)
+    }
+
+    pub fn put<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::PUT, url)
+    }
+
+    pub fn post<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::POST, url)
+    }
+
+    pub fn patch<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::PATCH, url)
+    }
+
+    pub fn delete<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::DELETE, url)
+    }
+
+    pub fn options<U>(&self, url: U) -> TracedClientRequest
+    where
+        Uri: TryFrom<U>,
+        <Uri as TryFrom<U>>::Error: Into<HttpError>,
+    {
+        self.request(Method::OPTIONS, url)
+    }
+}
+
+impl From<Client> for TracedClient {
+    fn from(client: Client) -> Self {
+        TracedClient {
+            client,
+            mode: SpanMode::default(),
+            url_redactor: UrlRedactor::default(),
+        }
+    }
+}
+
+impl fmt::Debug for TracedClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TracedClient")
+            .field("mode", &self.mode)
+            .field("url_redactor", &self.url_redactor)
+            .finish()
+    }
+}
+
+/// A [`ClientRequest`] instrumented with the current [`Span`] when sent.
+#[derive(Debug)]
+pub struct TracedClientRequest {
+    request: ClientRequest,
+    mode: SpanMode,
+    url_redactor: UrlRedactor,
+}
+
+impl From<ClientRequest> for TracedClientRequest {
+    fn from(request: ClientRequest) -> Self {
+        TracedClientRequest {
+            request,
+            mode: SpanMode::default(),
+            url_redactor: UrlRedactor::default(),
+        }
+    }
+}
+
+impl TracedClientRequest {
+    pub fn insert_header(self, header: impl TryIntoHeaderPair) -> Self {
+        self.map(|request| request.insert_header(header))
+    }
+
+    pub fn insert_header_if_none(self, header: impl TryIntoHeaderPair) -> Self {
+        self.map(|request| request.insert_header_if_none(header))
+    }
+
+    pub fn append_header(self, header: impl TryIntoHeaderPair) -> Self {
+        self.map(|request| request.append_header(header))
+    }
+
+    pub fn content_type<V>(self, value: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<HttpError>,
+    {
+        self.map(|request| request.content_type(value))
+    }
+
+    pub fn basic_auth(self, username: impl fmt::Display, password: impl fmt::Display) -> Self {
+        self.map(|request| request.basic_auth(username, password))
+    }
+
+    pub fn bearer_auth(self, token: impl fmt::Display) -> Self {
+        self.map(|request| request.bearer_auth(token))
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.map(|request| request.timeout(timeout))
+    }
+
+    pub fn no_decompress(self) -> Self {
+        self.map(|request| request.no_decompress())
+    }
+
+    /// Apply any other [`ClientRequest`] builder method.
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(ClientRequest) -> ClientRequest,
+    {
+        self.request = f(self.request);
+        self
+    }
+
+    pub fn send(self) -> impl Future<Output = AwcResult> {
+        let request = self.instrument();
+        async move {
+            match request {
+                Instrumented::OpenTelemetry(request) => request.send().await,
+                Instrumented::Tracing(request) => request.send().await,
+            }
+        }
+    }
+
+    pub fn send_body<B>(self, body: B) -> impl Future<Output = AwcResult>
+    where
+        B: MessageBody + 'static,
+    {
+        let request = self.instrument();
+        async move {
+            match request {
+                Instrumented::OpenTelemetry(request) => request.send_body(body).await,
+                Instrumented::Tracing(request) => request.send_body(body).await,
+            }
+        }
+    }
+
+    pub fn send_form<T: Serialize>(self, value: &T) -> impl Future<Output = AwcResult> + '_ {
+        let request = self.instrument();
+        async move {
+            match request {
+                Instrumented::OpenTelemetry(request) => request.send_form(value).await,
+                Instrumented::Tracing(request) => request.send_form(value).await,
+            }
+        }
+    }
+
+    pub fn send_json<T: Serialize>(self, value: &T) -> impl Future<Output = AwcResult> + '_ {
+        let request = self.instrument();
+        async move {
+            match request {
+                Instrumented::OpenTelemetry(request) => request.send_json(value).await,
+                Instrumented::Tracing(request) => request.send_json(value).await,
+            }
+        }
+    }
+
+    pub fn send_stream<S, E>(self, stream: S) -> impl Future<Output = AwcResult>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin + 'static,
+        E: std::error::Error + Into<actix_http::Error> + 'static,
+    {
+        let request = self.instrument();
+        async move {
+            match request {
+                Instrumented::OpenTelemetry(request) => request.send_stream(stream).await,
+                Instrumented::Tracing(request) => request.send_stream(stream).await,
+            }
+        }
+    }
+
+    /// Send with retries, see [`TracingClientExt::with_retry`].
+    pub fn with_retry(self, policy: RetryPolicy) -> RetryClientRequest {
+        RetryClientRequest::new(self.request, policy, self.url_redactor)
+    }
+
+    // Called before the returned futures are polled so the current span is the caller's.
+    fn instrument(self) -> Instrumented {
+        match self.mode {
+            SpanMode::OpenTelemetry => Instrumented::OpenTelemetry(
+                self.request
+                    .instrument_current()
+                    .with_url_redactor(self.url_redactor),
+            ),
+            SpanMode::Tracing => {
+                Instrumented::Tracing(SpannedClientRequest::new(self.request, self.url_redactor))
+            }
+        }
+    }
+}
+
+// Only lives until the request is sent, so the size difference does not matter.
+#[allow(clippy::large_enum_variant)]
+enum Instrumented {
+    OpenTelemetry(InstrumentedClientRequest),
+    Tracing(SpannedClientRequest),
+}
+
+/// A [`ClientRequest`] recorded as a `tracing` span.
+///
+/// The span carries the `http.method`, `http.url`, `http.flavor`, `http.status_code` and
+/// `otel.kind = client` fields, and its OpenTelemetry context is propagated to the server.
+#[derive(Debug)]
+pub struct SpannedClientRequest {
+    request: ClientRequest,
+    span: Span,
+}
+
+impl SpannedClientRequest {
+    fn new(request: ClientRequest, url_redactor: UrlRedactor) -> Self {
+        let span = client_span(
+            &Span::current(),
+            request.get_method(),
+            request.get_uri(),
+            &url_redactor.redact(request.get_uri()),
+            *request.get_version(),
+            *request.get_peer_addr(),
+            None,
+        );
+
+        SpannedClientRequest { request, span }
+    }
+
+    /// Redact the `http.url` of the span, by default only credentials are removed.
+    pub fn with_url_redactor(self, url_redactor: UrlRedactor) -> Self {
+        self.span.record(
+            "http.url",
+            display(url_redactor.redact(self.request.get_uri())),
+        );
+        self
+    }
+
+    /// The span recording this request.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub async fn send(self) -> AwcResult {
+        self.trace_request(|request| request.send()).await
+    }
+
+    pub async fn send_body<B>(self, body: B) -> AwcResult
+    where
+        B: MessageBody + 'static,
+    {
+        self.trace_request(|request| request.send_body(body)).await
+    }
+
+    pub async fn send_form<T: Serialize>(self, value: &T) -> AwcResult {
+        self.trace_request(|request| request.send_form(value)).await
+    }
+
+    pub async fn send_json<T: Serialize>(self, value: &T) -> AwcResult {
+        self.trace_request(|request| request.send_json(value)).await
+    }
+
+    pub async fn send_stream<S, E>(self, stream: S) -> AwcResult
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin + 'static,
+        E: std::error::Error + Into<actix_http::Error> + 'static,
+    {
+        self.trace_request(|request| request.send_stream(stream))
+            .await
+    }
+
+    async fn trace_request<F, R>(mut self, f: F) -> AwcResult
+    where
+        F: FnOnce(ClientRequest) -> R,
+        R: Future<Output = AwcResult>,
+    {
+        inject_context(&self.span, self.request.headers_mut());
+
+        let span = self.span;
+        let result = f(self.request).instrument(span.clone()).await;
+        record_result(&span, &result);
+        result
+    }
+}
+
+fn client_span(
+    parent: &Span,
+    method: &Method,
+    uri: &Uri,
+    url: &str,
+    version: Version,
+    peer_addr: Option<SocketAddr>,
+    route_formatter: Option<&dyn RouteFormatter>,
+) -> Span {
+    let span = tracing::info_span!(
+        parent: parent,
+        "HTTP request",
+        otel.name = %span_name(method, uri, route_formatter),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        http.method = %method,
+        http.url = %url,
+        http.flavor = %format!("{:?}", version).replace("HTTP/", ""),
+        http.status_code = Empty,
+        http.resend_count = Empty,
+        net.peer.ip = Empty,
+        exception.message = Empty,
+        exception.details = Empty,
+    );
+    if let Some(peer_addr) = peer_addr {
+        span.record("net.peer.ip", display(peer_addr));
+    }
+    span
+}
+
+// `METHOD scheme://host:port/path`, leaving out credentials and the query, with the path
+// formatted by `route_formatter` if any.
+fn span_name(method: &Method, uri: &Uri, route_formatter: Option<&dyn RouteFormatter>) -> String {
+    let mut name = format!("{} ", method);
+    if let Some(scheme) = uri.scheme_str() {
+        let _ = write!(name, "{}://", scheme);
+    }
+    if let Some(host) = uri.host() {
+        name.push_str(host);
+    }
+    if let Some(port) = uri.port_u16() {
+        let _ = write!(name, ":{}", port);
+    }
+    match route_formatter {
+        Some(route_formatter) => name.push_str(&route_formatter.format(uri.path())),
+        None => name.push_str(uri.path()),
+    }
+    name
+}
+
+fn inject_context(span: &Span, headers: &mut HeaderMap) {
+    let cx = span.context();
+    // the context only carries the span context, so failures are recorded on `span` itself
+    let mut carrier = ActixClientCarrier::with_failure_handler(headers, |msg| {
+        span.in_scope(|| tracing::warn!(exception.message = %msg, "propagation.inject.failed"))
+    });
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, &mut carrier));
+}
+
+fn record_result(span: &Span, result: &AwcResult) {
+    match result {
+        Ok(res) => {
+            let status = res.status();
+            // tracing-opentelemetry records unsigned integers as strings
+            span.record("http.status_code", i64::from(status.as_u16()));
+            // since we are the client, we MUST treat 4xx as error
+            if status.is_client_error() || status.is_server_error() {
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+        Err(err) => {
+            span.record("otel.status_code", "ERROR");
+            span.record("exception.message", display(err));
+            span.record("exception.details", tracing::field::debug(err));
+            // tracing-opentelemetry only adds events to the current span
+            span.in_scope(|| {
+                tracing::error!(
+                    exception.r#type = std::any::type_name::<SendRequestError>(),
+                    exception.message = %err,
+                    exception.stacktrace = %exception_stacktrace(err),
+                    "exception"
+                )
+            });
+        }
+    }
 }
@@ -0,0 +1,323 @@
+use super::{client_span, inject_context, record_result, span_name, AwcResult, UrlRedactor};
+use actix_web::web::Bytes;
+use actix_web_opentelemetry::RouteFormatter;
+use awc::error::{FreezeRequestError, SendRequestError};
+use awc::http::header::HeaderMap;
+use awc::http::{Method, StatusCode, Uri, Version};
+use awc::{ClientRequest, FrozenClientRequest, FrozenSendBuilder};
+use futures_util::{Future, Stream};
+use serde::Serialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::field::{display, Empty};
+use tracing::{Instrument, Span};
+
+/// When and how often [`RetryClientRequest`] resends a request.
+///
+/// By default up to 3 retries are made for connect errors, timeouts, `429` and `5xx`
+/// responses, backing off exponentially from 100ms up to 10s. Only requests with an
+/// idempotent method (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and `TRACE`) are retried
+/// unless [`retry_if`](RetryPolicy::retry_if) is set.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    timeout: Option<Duration>,
+    retry_if: Option<RetryIf>,
+}
+
+type RetryIf = Arc<dyn Fn(&AwcResult) -> bool + Send + Sync>;
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            timeout: None,
+            retry_if: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_retries", &self.max_retries)
+            .field("backoff", &self.backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy {
+            max_retries,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Delay before the first retry, doubled for every further retry up to `max_backoff`.
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Timeout of each attempt, overriding the client and request timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Decide which results are retried, whatever the method of the request.
+    pub fn retry_if<F>(mut self, retry_if: F) -> Self
+    where
+        F: Fn(&AwcResult) -> bool + Send + Sync + 'static,
+    {
+        self.retry_if = Some(Arc::new(retry_if));
+        self
+    }
+
+    fn is_retryable(&self, method: &Method, result: &AwcResult) -> bool {
+        match &self.retry_if {
+            Some(retry_if) => retry_if(result),
+            None => is_idempotent(method) && is_retryable(result),
+        }
+    }
+
+    fn delay(&self, resend_count: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(resend_count.saturating_sub(1)))
+            .min(self.max_backoff)
+    }
+}
+
+fn is_retryable(result: &AwcResult) -> bool {
+    match result {
+        Ok(res) => res.status().is_server_error() || res.status() == StatusCode::TOO_MANY_REQUESTS,
+        Err(SendRequestError::Connect(_))
+        | Err(SendRequestError::Send(_))
+        | Err(SendRequestError::Timeout) => true,
+        Err(_) => false,
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// A request sent according to a [`RetryPolicy`].
+///
+/// The logical request is recorded as one span with the final `http.status_code`,
+/// `http.resend_count` and `retry.outcome`, each attempt as a child client span. Backoff
+/// delays and fired timeouts are recorded as events.
+pub struct RetryClientRequest {
+    request: Result<FrozenClientRequest, FreezeRequestError>,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    peer_addr: Option<SocketAddr>,
+    policy: RetryPolicy,
+    url: String,
+    route_formatter: Option<Box<dyn RouteFormatter>>,
+    span: Span,
+}
+
+impl fmt::Debug for RetryClientRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryClientRequest")
+            .field("policy", &self.policy)
+            .field("route_formatter", &self.route_formatter)
+            .field("span", &self.span)
+            .finish()
+    }
+}
+
+impl RetryClientRequest {
+    pub(super) fn new(
+        request: ClientRequest,
+        policy: RetryPolicy,
+        url_redactor: UrlRedactor,
+    ) -> Self {
+        let method = request.get_method().clone();
+        let uri = request.get_uri().clone();
+        let url = url_redactor.redact(&uri);
+        let span = tracing::info_span!(
+            "HTTP request",
+            otel.name = %span_name(&method, &uri, None),
+            otel.kind = "internal",
+            otel.status_code = Empty,
+            http.method = %method,
+            http.url = %url,
+            http.status_code = Empty,
+            http.resend_count = Empty,
+            retry.outcome = Empty,
+            exception.message = Empty,
+            exception.details = Empty,
+        );
+        let version = *request.get_version();
+        let peer_addr = *request.get_peer_addr();
+        let request = match policy.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+
+        RetryClientRequest {
+            request: request.freeze(),
+            method,
+            uri,
+            version,
+            peer_addr,
+            policy,
+            url,
+            route_formatter: None,
+            span,
+        }
+    }
+
+    /// Redact the `http.url` of the spans, by default only credentials are removed.
+    pub fn with_url_redactor(mut self, url_redactor: UrlRedactor) -> Self {
+        self.url = url_redactor.redact(&self.uri);
+        self.span.record("http.url", display(&self.url));
+        self
+    }
+
+    /// Format the path in the span names, e.g. to leave out ids.
+    pub fn with_route_formatter<R: RouteFormatter + 'static>(mut self, route_formatter: R) -> Self {
+        self.span.record(
+            "otel.name",
+            display(span_name(&self.method, &self.uri, Some(&route_formatter))),
+        );
+        self.route_formatter = Some(Box::new(route_formatter));
+        self
+    }
+
+    /// The span recording the logical request.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub async fn send(self) -> AwcResult {
+        self.send_attempts(|request| request.send()).await
+    }
+
+    pub async fn send_body(self, body: impl Into<Bytes>) -> AwcResult {
+        let body = body.into();
+        self.send_attempts(|request| request.send_body(body.clone()))
+            .await
+    }
+
+    pub async fn send_form<T: Serialize>(self, value: &T) -> AwcResult {
+        self.send_attempts(|request| request.send_form(value)).await
+    }
+
+    pub async fn send_json<T: Serialize>(self, value: &T) -> AwcResult {
+        self.send_attempts(|request| request.send_json(value)).await
+    }
+
+    /// Send a streaming body, created by `stream` for every attempt since a stream cannot be
+    /// resent.
+    pub async fn send_stream<F, S, E>(self, stream: F) -> AwcResult
+    where
+        F: Fn() -> S,
+        S: Stream<Item = Result<Bytes, E>> + 'static,
+        E: Into<Box<dyn std::error::Error>> + 'static,
+    {
+        self.send_attempts(|request| request.send_stream(stream()))
+            .await
+    }
+
+    async fn send_attempts<F, R>(self, f: F) -> AwcResult
+    where
+        F: Fn(FrozenSendBuilder) -> R,
+        R: Future<Output = AwcResult>,
+    {
+        let RetryClientRequest {
+            request,
+            method,
+            uri,
+            version,
+            peer_addr,
+            policy,
+            url,
+            route_formatter,
+            span,
+        } = self;
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                let result = Err(err.into());
+                record_result(&span, &result);
+                return result;
+            }
+        };
+
+        let mut resend_count = 0;
+        loop {
+            let attempt = client_span(
+                &span,
+                &method,
+                &uri,
+                &url,
+                version,
+                peer_addr,
+                route_formatter.as_deref(),
+            );
+            if resend_count > 0 {
+                attempt.record("http.resend_count", i64::from(resend_count));
+            }
+
+            let mut headers = HeaderMap::new();
+            inject_context(&attempt, &mut headers);
+            let started = Instant::now();
+            let result = f(request.extra_headers(headers))
+                .instrument(attempt.clone())
+                .await;
+            record_result(&attempt, &result);
+            if let Err(SendRequestError::Timeout) = result {
+                // without a policy timeout the client or request timeout fired, which awc
+                // does not expose, so it is measured instead
+                let timeout = policy.timeout.unwrap_or_else(|| started.elapsed());
+                // tracing-opentelemetry only adds events to the current span
+                attempt.in_scope(|| {
+                    tracing::warn!(timeout_ms = timeout.as_millis() as i64, "request timed out")
+                });
+            }
+
+            let retryable = policy.is_retryable(&method, &result);
+            if !retryable || resend_count >= policy.max_retries {
+                let outcome = match &result {
+                    _ if retryable => "exhausted",
+                    Ok(res) if res.status().as_u16() < 400 => "success",
+                    _ => "failed",
+                };
+                record_result(&span, &result);
+                span.record("http.resend_count", i64::from(resend_count));
+                span.record("retry.outcome", display(outcome));
+                return result;
+            }
+            // release the connection of a failed response before backing off
+            drop(result);
+
+            resend_count += 1;
+            let delay = policy.delay(resend_count);
+            span.in_scope(|| {
+                tracing::info!(
+                    http.resend_count = i64::from(resend_count),
+                    retry.delay_ms = delay.as_millis() as i64,
+                    "retrying request"
+                )
+            });
+            actix_rt::time::sleep(delay).await;
+        }
+    }
+}
@@ -1,16 +1,24 @@
 pub mod awc;
 mod layer;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod middleware;
 
 use crate::layer::EventLayer;
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, MetricsExporter};
 
 use opentelemetry::global;
 use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::Resource;
+use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
 use tracing::Level;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
-pub use actix_web_opentelemetry::RequestTracing;
+pub use crate::middleware::{Telemetry, TelemetryRootSpanBuilder};
+pub use actix_web_opentelemetry::{RequestTracing, RouteFormatter};
 pub use opentelemetry::trace::TraceContextExt;
 pub use opentelemetry::Context;
 pub use tracing;
@@ -21,6 +29,82 @@ pub use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::format::FmtSpan;
 
 pub async fn configure(service_name: &'static str) {
+    TelemetryConfig::new(service_name).init().await;
+}
+
+/// Builder for the pipelines installed by [`configure`].
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    service_name: &'static str,
+    #[cfg(feature = "metrics")]
+    metrics: Option<MetricsExporter>,
+}
+
+/// Handles to the installed pipelines, keep it alive for as long as telemetry is needed.
+#[derive(Debug)]
+pub struct TelemetryHandle {
+    #[cfg(feature = "metrics")]
+    metrics: Option<Metrics>,
+}
+
+impl TelemetryHandle {
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// A [`Telemetry`] middleware recording to the installed pipelines.
+    pub fn middleware(&self) -> Telemetry {
+        let telemetry = Telemetry::new();
+        #[cfg(feature = "metrics")]
+        let telemetry = match &self.metrics {
+            Some(metrics) => telemetry.with_metrics(metrics.request_metrics()),
+            None => telemetry,
+        };
+        telemetry
+    }
+}
+
+impl TelemetryConfig {
+    pub fn new(service_name: &'static str) -> Self {
+        TelemetryConfig {
+            service_name,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+
+    /// Install a global meter provider sharing the trace resource.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, exporter: MetricsExporter) -> Self {
+        self.metrics = Some(exporter);
+        self
+    }
+
+    pub async fn init(self) -> TelemetryHandle {
+        let service_name = self.service_name;
+        let resource = resource(service_name);
+        install_tracing(service_name, resource.clone()).await;
+
+        TelemetryHandle {
+            #[cfg(feature = "metrics")]
+            metrics: self
+                .metrics
+                .map(|exporter| Metrics::install(service_name, resource, exporter)),
+        }
+    }
+}
+
+fn resource(service_name: &'static str) -> Resource {
+    let mut attributes = vec![SERVICE_NAME.string(service_name)];
+    if let Some(version) = option_env!("SHORT_SHA") {
+        attributes.push(SERVICE_VERSION.string(version));
+    }
+    Resource::new(attributes)
+}
+
+#[allow(unused_variables)]
+async fn install_tracing(service_name: &'static str, resource: Resource) {
     global::set_text_map_propagator(TraceContextPropagator::new());
 
     let env_filter = EnvFilter::try_from_default_env()
@@ -36,6 +120,7 @@ pub async fn configure(service_name: &'static str) {
     #[cfg(feature = "jaeger")]
     let tracer = opentelemetry_jaeger::new_pipeline()
         .with_service_name(service_name)
+        .with_trace_config(opentelemetry::sdk::trace::config().with_resource(resource))
         .install_simple()
         .expect("jaeger");
 
@@ -57,11 +142,12 @@ pub async fn configure(service_name: &'static str) {
 
         let provider = trace::TracerProvider::builder()
             .with_simple_exporter(exporter)
+            .with_config(trace::config().with_resource(resource))
             .build();
         let tracer = provider.versioned_tracer(
             service_name,
             option_env!("SHORT_SHA"),
-            Some("https://opentelemetry.io/schema/1.0.0")
+            Some("https://opentelemetry.io/schema/1.0.0"),
         );
 
         let _ = global::set_tracer_provider(provider);
@@ -75,7 +161,11 @@ pub async fn configure(service_name: &'static str) {
         use opentelemetry::sdk::trace::{Config, Sampler};
 
         stdout::new_pipeline()
-            .with_trace_config(Config::default().with_sampler(Sampler::AlwaysOn))
+            .with_trace_config(
+                Config::default()
+                    .with_sampler(Sampler::AlwaysOn)
+                    .with_resource(resource),
+            )
             .install_simple()
     };
 
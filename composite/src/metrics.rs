@@ -0,0 +1,112 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+use opentelemetry::metrics::registry::RegistryMeterProvider;
+use opentelemetry::metrics::{Meter, MeterProvider};
+use opentelemetry::sdk::Resource;
+use opentelemetry_prometheus::PrometheusExporter;
+
+pub use actix_web_opentelemetry::RequestMetrics;
+
+pub type MetricsRoute = fn(&ServiceRequest) -> bool;
+
+/// Where [`Metrics`] are exported to.
+#[derive(Debug, Clone)]
+pub enum MetricsExporter {
+    /// Serve metrics for prometheus to scrape at `GET /metrics`.
+    Prometheus,
+    /// Push metrics to an OTLP collector over gRPC.
+    #[cfg(feature = "otlp_metrics")]
+    Otlp { endpoint: String },
+}
+
+/// The installed metrics pipeline.
+///
+/// With [`MetricsExporter::Otlp`] metrics are only pushed while this is alive.
+#[derive(Debug)]
+pub struct Metrics {
+    service_name: &'static str,
+    provider: RegistryMeterProvider,
+    exporter: Option<PrometheusExporter>,
+    #[cfg(feature = "otlp_metrics")]
+    _controller: Option<opentelemetry::sdk::metrics::PushController>,
+}
+
+impl Metrics {
+    /// Install the metrics pipeline as the global meter provider, without the tracing
+    /// pipeline of [`TelemetryConfig`](crate::TelemetryConfig).
+    pub fn install(
+        service_name: &'static str,
+        resource: Resource,
+        exporter: MetricsExporter,
+    ) -> Self {
+        match exporter {
+            MetricsExporter::Prometheus => {
+                let exporter = opentelemetry_prometheus::exporter()
+                    .with_resource(resource)
+                    .init();
+
+                Metrics {
+                    service_name,
+                    provider: exporter.provider().expect("prometheus metrics"),
+                    exporter: Some(exporter),
+                    #[cfg(feature = "otlp_metrics")]
+                    _controller: None,
+                }
+            }
+            #[cfg(feature = "otlp_metrics")]
+            MetricsExporter::Otlp { endpoint } => {
+                use opentelemetry::KeyValue;
+                use opentelemetry_otlp::WithExportConfig;
+
+                let controller = opentelemetry_otlp::new_pipeline()
+                    .metrics(tokio::spawn, opentelemetry::util::tokio_interval_stream)
+                    .with_exporter(
+                        opentelemetry_otlp::new_exporter()
+                            .tonic()
+                            .with_endpoint(endpoint),
+                    )
+                    .with_resource(
+                        resource
+                            .iter()
+                            .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+                    )
+                    .build()
+                    .expect("otlp metrics");
+
+                Metrics {
+                    service_name,
+                    provider: controller.provider(),
+                    exporter: None,
+                    _controller: Some(controller),
+                }
+            }
+        }
+    }
+
+    /// A meter from the installed meter provider, named after the service.
+    pub fn meter(&self) -> Meter {
+        self.provider
+            .meter(self.service_name, option_env!("SHORT_SHA"))
+    }
+
+    pub fn exporter(&self) -> Option<&PrometheusExporter> {
+        self.exporter.as_ref()
+    }
+
+    /// Request metrics middleware recording to [`Metrics::meter`].
+    ///
+    /// With [`MetricsExporter::Prometheus`] it also serves `GET /metrics`.
+    pub fn request_metrics(&self) -> RequestMetrics<MetricsRoute> {
+        RequestMetrics::new(
+            self.meter(),
+            self.exporter
+                .as_ref()
+                .map(|_| metrics_route as MetricsRoute),
+            self.exporter.clone(),
+        )
+    }
+}
+
+fn metrics_route(req: &ServiceRequest) -> bool {
+    req.path() == "/metrics" && req.method() == Method::GET
+}
@@ -0,0 +1,183 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage, ResponseError};
+use futures_util::future::LocalBoxFuture;
+use std::sync::Arc;
+use tracing::Span;
+use tracing_actix_web::{
+    root_span, DefaultRootSpanBuilder, RootSpanBuilder, StatusClassifier, TracingLogger,
+};
+
+use actix_web_opentelemetry::RouteFormatter;
+#[cfg(feature = "metrics")]
+use {
+    crate::metrics::{MetricsRoute, RequestMetrics},
+    actix_web_opentelemetry::RequestMetricsMiddleware,
+};
+
+#[cfg(feature = "metrics")]
+type Inner<S> = RequestMetricsMiddleware<S, MetricsRoute>;
+#[cfg(not(feature = "metrics"))]
+type Inner<S> = S;
+
+type Logger<S> =
+    <TracingLogger<TelemetryRootSpanBuilder> as Transform<Inner<S>, ServiceRequest>>::Transform;
+
+/// Server telemetry in a single middleware.
+///
+/// Every request gets exactly one server span (exported through `tracing-opentelemetry`),
+/// a [`RequestId`](tracing_actix_web::RequestId) and, with the `metrics` feature, request
+/// metrics. Span and metrics share the same route naming.
+///
+/// This replaces wrapping [`TracingLogger`] and [`RequestTracing`](crate::RequestTracing)
+/// separately, which produces two server spans per request.
+#[derive(Clone, Debug, Default)]
+pub struct Telemetry {
+    route_formatter: Option<SharedRouteFormatter>,
+    status_classifier: Option<SharedStatusClassifier>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<RequestMetrics<MetricsRoute>>,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Telemetry::default()
+    }
+
+    /// Format the `http.route` of the span and the `route` label of the metrics.
+    pub fn with_route_formatter<R>(mut self, route_formatter: R) -> Self
+    where
+        R: RouteFormatter + Send + Sync + 'static,
+    {
+        self.route_formatter = Some(SharedRouteFormatter(Arc::new(route_formatter)));
+        self
+    }
+
+    /// Decide which requests are recorded as errors on the span, see
+    /// [`TracingLogger::with_status_classifier`].
+    pub fn with_status_classifier<C>(mut self, status_classifier: C) -> Self
+    where
+        C: StatusClassifier + Send + Sync + 'static,
+    {
+        self.status_classifier = Some(SharedStatusClassifier(Arc::new(status_classifier)));
+        self
+    }
+
+    /// Record request metrics with the given middleware, see
+    /// [`Metrics::request_metrics`](crate::metrics::Metrics::request_metrics).
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: RequestMetrics<MetricsRoute>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Telemetry
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = <Logger<S> as Service<ServiceRequest>>::Response;
+    type Error = Error;
+    type Transform = TelemetryMiddleware<Logger<S>>;
+    type InitError = ();
+    type Future = LocalBoxFuture<'static, Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let route_formatter = self.route_formatter.clone();
+        let logger = match self.status_classifier.clone() {
+            Some(status_classifier) => TracingLogger::<TelemetryRootSpanBuilder>::new()
+                .with_status_classifier(status_classifier),
+            None => TracingLogger::new(),
+        };
+        #[cfg(feature = "metrics")]
+        let metrics = {
+            let metrics = self.metrics.clone().unwrap_or_default();
+            match &route_formatter {
+                Some(formatter) => metrics.with_route_formatter(formatter.clone()),
+                None => metrics,
+            }
+        };
+
+        Box::pin(async move {
+            #[cfg(feature = "metrics")]
+            let service = metrics.new_transform(service).await?;
+            let service = logger.new_transform(service).await?;
+
+            Ok(TelemetryMiddleware {
+                service,
+                route_formatter,
+            })
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct TelemetryMiddleware<S> {
+    service: S,
+    route_formatter: Option<SharedRouteFormatter>,
+}
+
+impl<S> Service<ServiceRequest> for TelemetryMiddleware<S>
+where
+    S: Service<ServiceRequest>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(formatter) = &self.route_formatter {
+            req.extensions_mut().insert(formatter.clone());
+        }
+        self.service.call(req)
+    }
+}
+
+/// [`RootSpanBuilder`] used by [`Telemetry`], applying its route formatter to the root span.
+pub struct TelemetryRootSpanBuilder;
+
+impl RootSpanBuilder for TelemetryRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = root_span!(request);
+        if let Some(formatter) = request.extensions().get::<SharedRouteFormatter>() {
+            let route = formatter.format(
+                &request
+                    .match_pattern()
+                    .unwrap_or_else(|| "default".to_string()),
+            );
+            span.record(
+                "otel.name",
+                tracing::field::display(format!("HTTP {} {}", request.method(), route)),
+            );
+            span.record("http.route", tracing::field::display(route));
+        }
+        span
+    }
+
+    fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SharedRouteFormatter(Arc<dyn RouteFormatter + Send + Sync>);
+
+impl RouteFormatter for SharedRouteFormatter {
+    fn format(&self, path: &str) -> String {
+        self.0.format(path)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SharedStatusClassifier(Arc<dyn StatusClassifier + Send + Sync>);
+
+impl StatusClassifier for SharedStatusClassifier {
+    fn classify(&self, status: StatusCode, error: Option<&dyn ResponseError>) -> Option<String> {
+        self.0.classify(status, error)
+    }
+}
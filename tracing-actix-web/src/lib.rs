@@ -232,6 +232,8 @@
 //!
 //! Check out the [relevant example in the GitHub repository](https://github.com/LukeMathWalker/tracing-actix-web/tree/main/examples/opentelemetry) for reference.
 //!
+//! `TracingLogger::with_trace_response` injects the OpenTelemetry context of the root span into responses, as a `traceresponse` and/or `Server-Timing` header, to link browser requests to the backend trace.
+//!
 //! [root span]: crate::RootSpan
 //! [`actix-web`]: https://docs.rs/actix-web/4.0.0-beta.13/actix_web/index.html
 mod middleware;
@@ -239,6 +241,15 @@ mod request_id;
 mod root_span;
 mod root_span_builder;
 
+#[cfg(any(
+    feature = "opentelemetry_0_13",
+    feature = "opentelemetry_0_14",
+    feature = "opentelemetry_0_15",
+    feature = "opentelemetry_0_16",
+    feature = "opentelemetry_0_17"
+))]
+pub use actix_web_telemetry_common::TraceResponse;
+pub use actix_web_telemetry_common::{DefaultStatusClassifier, StatusClassifier, TrustedProxies};
 pub use middleware::TracingLogger;
 pub use request_id::RequestId;
 pub use root_span::RootSpan;
@@ -1,4 +1,6 @@
-use crate::{DefaultRootSpanBuilder, RequestId, RootSpan, RootSpanBuilder};
+use crate::{
+    DefaultRootSpanBuilder, RequestId, RootSpan, RootSpanBuilder, StatusClassifier, TrustedProxies,
+};
 use actix_web::body::{BodySize, MessageBody};
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
 use actix_web::http::StatusCode;
@@ -6,9 +8,73 @@ use actix_web::web::Bytes;
 use actix_web::{Error, HttpMessage, ResponseError};
 use std::future::{ready, Future, Ready};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use tracing::Span;
 
+#[cfg(any(
+    feature = "opentelemetry_0_13",
+    feature = "opentelemetry_0_14",
+    feature = "opentelemetry_0_15",
+    feature = "opentelemetry_0_16",
+    feature = "opentelemetry_0_17"
+))]
+use crate::TraceResponse;
+
+#[cfg(any(
+    feature = "opentelemetry_0_13",
+    feature = "opentelemetry_0_14",
+    feature = "opentelemetry_0_15",
+    feature = "opentelemetry_0_16",
+    feature = "opentelemetry_0_17"
+))]
+// Inject the OpenTelemetry context of `span` into the response or error of the service.
+fn inject<B>(
+    trace_response: &TraceResponse,
+    span: &Span,
+    cross_origin: bool,
+    outcome: Result<ServiceResponse<B>, Error>,
+) -> Result<ServiceResponse<B>, Error> {
+    let traceparent = match crate::otel::traceparent(span) {
+        Some(traceparent) => traceparent,
+        None => return outcome,
+    };
+    match outcome {
+        Ok(mut response) => {
+            trace_response.inject(&traceparent, &mut response);
+            Ok(response)
+        }
+        Err(err) => Err(trace_response.inject_error(&traceparent, cross_origin, err)),
+    }
+}
+
+// Without OpenTelemetry there is no context to inject into responses.
+#[cfg(not(any(
+    feature = "opentelemetry_0_13",
+    feature = "opentelemetry_0_14",
+    feature = "opentelemetry_0_15",
+    feature = "opentelemetry_0_16",
+    feature = "opentelemetry_0_17"
+)))]
+#[derive(Clone, Copy)]
+enum TraceResponse {}
+
+#[cfg(not(any(
+    feature = "opentelemetry_0_13",
+    feature = "opentelemetry_0_14",
+    feature = "opentelemetry_0_15",
+    feature = "opentelemetry_0_16",
+    feature = "opentelemetry_0_17"
+)))]
+fn inject<B>(
+    trace_response: &TraceResponse,
+    _span: &Span,
+    _cross_origin: bool,
+    _outcome: Result<ServiceResponse<B>, Error>,
+) -> Result<ServiceResponse<B>, Error> {
+    match *trace_response {}
+}
+
 /// `TracingLogger` is a middleware to capture structured diagnostic when processing an HTTP request.
 /// Check the crate-level documentation for an in-depth introduction.
 ///
@@ -81,11 +147,19 @@ use tracing::Span;
 /// [`tracing`]: https://docs.rs/tracing
 pub struct TracingLogger<RootSpan: RootSpanBuilder> {
     root_span_builder: std::marker::PhantomData<RootSpan>,
+    trace_response: Option<TraceResponse>,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    status_classifier: Option<Arc<dyn StatusClassifier + Send + Sync>>,
 }
 
 impl<RootSpan: RootSpanBuilder> Clone for TracingLogger<RootSpan> {
     fn clone(&self) -> Self {
-        Self::new()
+        TracingLogger {
+            root_span_builder: Default::default(),
+            trace_response: self.trace_response,
+            trusted_proxies: self.trusted_proxies.clone(),
+            status_classifier: self.status_classifier.clone(),
+        }
     }
 }
 
@@ -99,8 +173,73 @@ impl<RootSpan: RootSpanBuilder> TracingLogger<RootSpan> {
     pub fn new() -> TracingLogger<RootSpan> {
         TracingLogger {
             root_span_builder: Default::default(),
+            trace_response: None,
+            trusted_proxies: None,
+            status_classifier: None,
         }
     }
+
+    /// Decide which requests are recorded as errors with the given [`StatusClassifier`], passed to
+    /// [`RootSpanBuilder::on_request_end_with_classifier`].
+    ///
+    /// By default, [`DefaultRootSpanBuilder`] records requests failing with an error as errors,
+    /// unless the status is a client error (4xx). [`DefaultStatusClassifier`](crate::DefaultStatusClassifier)
+    /// also records server errors (5xx) without an error.
+    ///
+    /// ```rust
+    /// use actix_web::App;
+    /// use tracing_actix_web::{DefaultStatusClassifier, TracingLogger};
+    ///
+    /// let app = App::new().wrap(TracingLogger::default().with_status_classifier(DefaultStatusClassifier));
+    /// ```
+    pub fn with_status_classifier<C>(mut self, status_classifier: C) -> Self
+    where
+        C: StatusClassifier + Send + Sync + 'static,
+    {
+        self.status_classifier = Some(Arc::new(status_classifier));
+        self
+    }
+
+    /// Record the client IP reported by the given [`TrustedProxies`] only, instead of trusting the
+    /// forwarding headers of any peer.
+    ///
+    /// ```rust
+    /// use actix_web::App;
+    /// use tracing_actix_web::{TracingLogger, TrustedProxies};
+    ///
+    /// let app = App::new().wrap(
+    ///     TracingLogger::default().with_trusted_proxies(TrustedProxies::new().trust_private_networks()),
+    /// );
+    /// ```
+    pub fn with_trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(Arc::new(trusted_proxies));
+        self
+    }
+
+    /// Inject the OpenTelemetry context of the root span into responses, see [`TraceResponse`].
+    ///
+    /// Errors returned by the wrapped service are replaced, see
+    /// [`TraceResponse`](TraceResponse#errors).
+    ///
+    /// ```rust
+    /// use actix_web::App;
+    /// use tracing_actix_web::{TraceResponse, TracingLogger};
+    ///
+    /// let app = App::new().wrap(
+    ///     TracingLogger::default().with_trace_response(TraceResponse::new().server_timing(true)),
+    /// );
+    /// ```
+    #[cfg(any(
+        feature = "opentelemetry_0_13",
+        feature = "opentelemetry_0_14",
+        feature = "opentelemetry_0_15",
+        feature = "opentelemetry_0_16",
+        feature = "opentelemetry_0_17"
+    ))]
+    pub fn with_trace_response(mut self, trace_response: TraceResponse) -> Self {
+        self.trace_response = Some(trace_response);
+        self
+    }
 }
 
 impl<S, B, RootSpan> Transform<S, ServiceRequest> for TracingLogger<RootSpan>
@@ -120,6 +259,9 @@ where
         ready(Ok(TracingLoggerMiddleware {
             service,
             root_span_builder: std::marker::PhantomData::default(),
+            trace_response: self.trace_response,
+            trusted_proxies: self.trusted_proxies.clone(),
+            status_classifier: self.status_classifier.clone(),
         }))
     }
 }
@@ -128,6 +270,9 @@ where
 pub struct TracingLoggerMiddleware<S, RootSpanBuilder> {
     service: S,
     root_span_builder: std::marker::PhantomData<RootSpanBuilder>,
+    trace_response: Option<TraceResponse>,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    status_classifier: Option<Arc<dyn StatusClassifier + Send + Sync>>,
 }
 
 #[allow(clippy::type_complexity)]
@@ -146,16 +291,23 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         req.extensions_mut().insert(RequestId::generate());
+        if let Some(trusted_proxies) = &self.trusted_proxies {
+            req.extensions_mut().insert(trusted_proxies.clone());
+        }
         let root_span = RootSpanType::on_request_start(&req);
 
         let root_span_wrapper = RootSpan::new(root_span.clone());
         req.extensions_mut().insert(root_span_wrapper);
 
+        let cross_origin = req.headers().contains_key(actix_web::http::header::ORIGIN);
         let fut = root_span.in_scope(|| self.service.call(req));
 
         TracingResponse {
             fut,
             span: root_span,
+            trace_response: self.trace_response,
+            cross_origin,
+            status_classifier: self.status_classifier.clone(),
             _root_span_type: std::marker::PhantomData,
         }
     }
@@ -167,6 +319,9 @@ pub struct TracingResponse<F, RootSpanType> {
     #[pin]
     fut: F,
     span: Span,
+    trace_response: Option<TraceResponse>,
+    cross_origin: bool,
+    status_classifier: Option<Arc<dyn StatusClassifier + Send + Sync>>,
     _root_span_type: std::marker::PhantomData<RootSpanType>,
 }
 
@@ -191,17 +346,31 @@ where
 
         let fut = this.fut;
         let span = this.span;
+        let trace_response = this.trace_response;
+        let cross_origin = *this.cross_origin;
+        let status_classifier = this.status_classifier;
 
         span.in_scope(|| match fut.poll(cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(outcome) => {
-                RootSpanType::on_request_end(Span::current(), &outcome);
+                match status_classifier {
+                    Some(status_classifier) => RootSpanType::on_request_end_with_classifier(
+                        Span::current(),
+                        &outcome,
+                        status_classifier.as_ref(),
+                    ),
+                    None => RootSpanType::on_request_end(Span::current(), &outcome),
+                }
 
                 #[cfg(feature = "emit_event_on_error")]
                 {
                     emit_event_on_error(&outcome);
                 }
 
+                let outcome = match trace_response {
+                    Some(trace_response) => inject(trace_response, span, cross_origin, outcome),
+                    None => outcome,
+                };
                 Poll::Ready(outcome.map(|service_response| {
                     service_response.map_body(|_, body| StreamSpan {
                         body,
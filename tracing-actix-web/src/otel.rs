@@ -72,3 +72,39 @@ pub(crate) fn set_otel_parent(req: &ServiceRequest, span: &tracing::Span) {
     // Attach to otel thread context for otel consumers
     req.extensions_mut().insert(context.attach());
 }
+
+/// The W3C `traceparent` representation of the OpenTelemetry context of `span`, if valid.
+pub(crate) fn traceparent(span: &tracing::Span) -> Option<String> {
+    use opentelemetry::trace::TraceContextExt as _;
+    use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+    let context = span.context();
+    let span_context = context.span().span_context().clone();
+    if !span_context.is_valid() {
+        return None;
+    }
+
+    let flags = if span_context.is_sampled() {
+        "01"
+    } else {
+        "00"
+    };
+
+    #[cfg(not(feature = "opentelemetry_0_17"))]
+    let traceparent = format!(
+        "00-{}-{}-{}",
+        span_context.trace_id().to_hex(),
+        span_context.span_id().to_hex(),
+        flags
+    );
+
+    #[cfg(feature = "opentelemetry_0_17")]
+    let traceparent = format!(
+        "00-{:032x}-{:016x}-{}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        flags
+    );
+
+    Some(traceparent)
+}
@@ -1,4 +1,4 @@
-use crate::root_span;
+use crate::{root_span, StatusClassifier};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::http::StatusCode;
 use actix_web::{Error, ResponseError};
@@ -11,6 +11,21 @@ use tracing::Span;
 pub trait RootSpanBuilder {
     fn on_request_start(request: &ServiceRequest) -> Span;
     fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>);
+
+    /// Record the outcome of the request, using `classifier` to decide whether it is an error.
+    ///
+    /// Called instead of [`on_request_end`](RootSpanBuilder::on_request_end) when a classifier is
+    /// set with [`TracingLogger::with_status_classifier`]. By default the classifier is ignored
+    /// and the status is left to `on_request_end`.
+    ///
+    /// [`TracingLogger::with_status_classifier`]: crate::TracingLogger::with_status_classifier
+    fn on_request_end_with_classifier<B>(
+        span: Span,
+        outcome: &Result<ServiceResponse<B>, Error>,
+        _classifier: &dyn StatusClassifier,
+    ) {
+        Self::on_request_end(span, outcome);
+    }
 }
 
 /// The default [`RootSpanBuilder`] for [`TracingLogger`].
@@ -20,7 +35,9 @@ pub trait RootSpanBuilder {
 /// - HTTP route (`http.route`), with templated parameters;
 /// - HTTP version (`http.flavor`);
 /// - HTTP host (`http.host`);
-/// - Client IP (`http.client_ip`);
+/// - Host name and port (`net.host.name` and `net.host.port`), the port defaulting to the port the server is bound to;
+/// - Client IP (`http.client_ip`), see [`TrustedProxies`](crate::TrustedProxies);
+/// - Peer IP (`net.peer.ip`);
 /// - User agent (`http.user_agent`);
 /// - Request path (`http.target`);
 /// - Status code (`http.status_code`);
@@ -28,7 +45,12 @@ pub trait RootSpanBuilder {
 /// - `Display` (`exception.message`) and `Debug` (`exception.details`) representations of the error, if there was an error;
 /// - [Request id](crate::RequestId) (`request_id`);
 /// - [OpenTelemetry trace identifier](https://github.com/open-telemetry/opentelemetry-specification/blob/main/specification/overview.md#spancontext) (`trace_id`). Empty if the feature is not enabled;
-/// - OpenTelemetry span kind, set to `server` (`otel.kind`).
+/// - OpenTelemetry span kind, set to `server` (`otel.kind`);
+/// - OpenTelemetry span status (`otel.status_code`), `ERROR` for requests failing with an error unless the status is a
+///   client error (4xx). With a [`StatusClassifier`] set by [`TracingLogger::with_status_classifier`], the status is
+///   decided by the classifier and the error description recorded as `otel.status_message`.
+///
+/// [`TracingLogger::with_status_classifier`]: crate::TracingLogger::with_status_classifier
 ///
 /// All field names follow [OpenTelemetry's semantic convention](https://github.com/open-telemetry/opentelemetry-specification/tree/main/specification/trace/semantic_conventions).
 ///
@@ -41,26 +63,83 @@ impl RootSpanBuilder for DefaultRootSpanBuilder {
     }
 
     fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
-        match &outcome {
+        record_outcome(&span, outcome);
+        let is_error = match outcome {
             Ok(response) => {
-                if let Some(error) = response.response().error() {
-                    // use the status code already constructed for the outgoing HTTP response
-                    handle_error(span, response.status(), error.as_response_error());
-                } else {
-                    let code: i32 = response.response().status().as_u16().into();
-                    span.record("http.status_code", &code);
-                    span.record("otel.status_code", &"OK");
-                }
-            }
-            Err(error) => {
-                let response_error = error.as_response_error();
-                handle_error(span, response_error.status_code(), response_error);
+                response.response().error().is_some() && !response.status().is_client_error()
             }
+            Err(error) => !error.as_response_error().status_code().is_client_error(),
         };
+        span.record("otel.status_code", if is_error { "ERROR" } else { "OK" });
+    }
+
+    /// Custom root span builders can classify their requests with the default fields too, with
+    /// any [`StatusClassifier`]:
+    ///
+    /// ```rust
+    /// use actix_web::dev::{ServiceResponse, ServiceRequest};
+    /// use actix_web::Error;
+    /// use tracing::Span;
+    /// use tracing_actix_web::{root_span, DefaultRootSpanBuilder, DefaultStatusClassifier, RootSpanBuilder};
+    ///
+    /// pub struct CustomRootSpanBuilder;
+    ///
+    /// impl RootSpanBuilder for CustomRootSpanBuilder {
+    ///     fn on_request_start(request: &ServiceRequest) -> Span {
+    ///         root_span!(request)
+    ///     }
+    ///
+    ///     fn on_request_end<B>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+    ///         DefaultRootSpanBuilder::on_request_end_with_classifier(span, outcome, &DefaultStatusClassifier);
+    ///     }
+    /// }
+    /// ```
+    fn on_request_end_with_classifier<B>(
+        span: Span,
+        outcome: &Result<ServiceResponse<B>, Error>,
+        classifier: &dyn StatusClassifier,
+    ) {
+        record_outcome(&span, outcome);
+        record_status(&span, classify(outcome, classifier));
     }
 }
 
-fn handle_error(span: Span, status_code: StatusCode, response_error: &dyn ResponseError) {
+// Record the status code of the response and the error the request failed with, if any.
+fn record_outcome<B>(span: &Span, outcome: &Result<ServiceResponse<B>, Error>) {
+    match &outcome {
+        Ok(response) => {
+            if let Some(error) = response.response().error() {
+                // use the status code already constructed for the outgoing HTTP response
+                handle_error(span, response.status(), error.as_response_error());
+            } else {
+                let code: i32 = response.response().status().as_u16().into();
+                span.record("http.status_code", &code);
+            }
+        }
+        Err(error) => {
+            let response_error = error.as_response_error();
+            handle_error(span, response_error.status_code(), response_error);
+        }
+    };
+}
+
+fn classify<B>(
+    outcome: &Result<ServiceResponse<B>, Error>,
+    classifier: &dyn StatusClassifier,
+) -> Option<String> {
+    match outcome {
+        Ok(response) => {
+            let error = response.response().error().map(Error::as_response_error);
+            classifier.classify(response.status(), error)
+        }
+        Err(error) => {
+            let response_error = error.as_response_error();
+            classifier.classify(response_error.status_code(), Some(response_error))
+        }
+    }
+}
+
+fn handle_error(span: &Span, status_code: StatusCode, response_error: &dyn ResponseError) {
     // pre-formatting errors is a workaround for https://github.com/tokio-rs/tracing/issues/1565
     let display = format!("{}", response_error);
     let debug = format!("{:?}", response_error);
@@ -69,10 +148,16 @@ fn handle_error(span: Span, status_code: StatusCode, response_error: &dyn Respon
     let code: i32 = status_code.as_u16().into();
 
     span.record("http.status_code", &code);
+}
 
-    if status_code.is_client_error() {
-        span.record("otel.status_code", &"OK");
-    } else {
-        span.record("otel.status_code", &"ERROR");
+fn record_status(span: &Span, error: Option<String>) {
+    match error {
+        Some(description) => {
+            span.record("otel.status_code", "ERROR");
+            span.record("otel.status_message", tracing::field::display(description));
+        }
+        None => {
+            span.record("otel.status_code", "OK");
+        }
     }
 }
@@ -74,6 +74,8 @@ macro_rules! root_span {
                 .unwrap_or_else(|| "default".into());
             let http_method = $crate::root_span_macro::private::http_method_str($request.method());
             let connection_info = $request.connection_info();
+            let (net_host_name, net_host_port) =
+                $crate::root_span_macro::private::net_host(&$request, connection_info.host());
             let request_id = $crate::root_span_macro::private::get_request_id($request);
             let span = $crate::root_span_macro::private::tracing::info_span!(
                 "HTTP request",
@@ -82,13 +84,17 @@ macro_rules! root_span {
                 http.flavor = %$crate::root_span_macro::private::http_flavor($request.version()),
                 http.scheme = %$crate::root_span_macro::private::http_scheme(connection_info.scheme()),
                 http.host = %connection_info.host(),
-                http.client_ip = %$request.connection_info().realip_remote_addr().unwrap_or(""),
+                net.host.name = %net_host_name,
+                net.host.port = net_host_port,
+                http.client_ip = %$crate::root_span_macro::private::client_ip(&$request),
+                net.peer.ip = %$crate::root_span_macro::private::peer_ip(&$request),
                 http.user_agent = %user_agent,
                 http.target = %$request.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
                 http.status_code = $crate::root_span_macro::private::tracing::field::Empty,
                 otel.name = %format!("HTTP {} {}", http_method, http_route),
                 otel.kind = "server",
                 otel.status_code = $crate::root_span_macro::private::tracing::field::Empty,
+                otel.status_message = $crate::root_span_macro::private::tracing::field::Empty,
                 trace_id = $crate::root_span_macro::private::tracing::field::Empty,
                 request_id = %request_id,
                 exception.message = $crate::root_span_macro::private::tracing::field::Empty,
@@ -116,10 +122,12 @@ pub mod private {
     //! in the code generated by the `root_span` macro.
     //! Items in this module are not part of the public interface of `tracing-actix-web` - they are considered
     //! implementation details and will change without notice in patch, minor and major releases.
-    use crate::RequestId;
+    use crate::{RequestId, TrustedProxies};
     use actix_web::dev::ServiceRequest;
     use actix_web::http::{Method, Version};
+    use actix_web_telemetry_common::split_host;
     use std::borrow::Cow;
+    use std::sync::Arc;
 
     pub use tracing;
 
@@ -179,6 +187,44 @@ pub mod private {
         }
     }
 
+    #[doc(hidden)]
+    pub fn net_host<'a>(request: &ServiceRequest, host: &'a str) -> (&'a str, u16) {
+        let (name, port) = split_host(host);
+        // without a port in the host, the port the server is bound to
+        (
+            name,
+            port.unwrap_or_else(|| request.app_config().local_addr().port()),
+        )
+    }
+
+    #[doc(hidden)]
+    pub fn client_ip(request: &ServiceRequest) -> String {
+        use actix_web::HttpMessage;
+
+        let trusted_proxies = request.extensions().get::<Arc<TrustedProxies>>().cloned();
+        match (trusted_proxies, request.peer_addr()) {
+            (Some(trusted_proxies), Some(peer_addr)) => trusted_proxies
+                .client_ip(peer_addr.ip(), request.headers())
+                .map(|client_ip| client_ip.to_string())
+                .unwrap_or_default(),
+            // without the peer address the forwarding headers cannot be trusted
+            (Some(_), None) => String::new(),
+            (None, _) => request
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or("")
+                .to_string(),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn peer_ip(request: &ServiceRequest) -> String {
+        request
+            .peer_addr()
+            .map(|peer_addr| peer_addr.ip().to_string())
+            .unwrap_or_default()
+    }
+
     #[doc(hidden)]
     pub fn generate_request_id() -> RequestId {
         RequestId::generate()
@@ -0,0 +1,202 @@
+#![cfg(feature = "opentelemetry_0_17")]
+use actix_web::dev::{fn_service, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App, Error, HttpResponse, ResponseError};
+use actix_web_telemetry_common::test_util::SpanCollector;
+use opentelemetry_0_17_pkg::sdk::{export::trace::SpanData, trace::TracerProvider};
+use opentelemetry_0_17_pkg::trace::{StatusCode as SpanStatus, TracerProvider as _};
+use opentelemetry_0_17_pkg::Key;
+use tracing::subscriber::DefaultGuard;
+use tracing_actix_web::{
+    root_span, DefaultStatusClassifier, RootSpanBuilder, StatusClassifier, TraceResponse,
+    TracingLogger, TrustedProxies,
+};
+use tracing_subscriber::layer::SubscriberExt;
+
+// Export the spans of the current thread to `collector`.
+fn install(collector: &SpanCollector) -> (TracerProvider, DefaultGuard) {
+    let provider = collector.provider();
+    let layer = tracing_opentelemetry_0_17_pkg::layer().with_tracer(provider.tracer("test"));
+    let guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+    (provider, guard)
+}
+
+#[derive(Debug)]
+struct RateLimitClassifier;
+
+impl StatusClassifier for RateLimitClassifier {
+    fn classify(&self, status: StatusCode, error: Option<&dyn ResponseError>) -> Option<String> {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => Some("rate limited".to_string()),
+            _ => DefaultStatusClassifier.classify(status, error),
+        }
+    }
+}
+
+#[actix_web::test]
+async fn records_status_of_custom_classifier() {
+    let collector = SpanCollector::default();
+    let _provider = install(&collector);
+    let app = test::init_service(
+        App::new()
+            .wrap(TracingLogger::default().with_status_classifier(RateLimitClassifier))
+            .route(
+                "/limited",
+                web::get().to(|| async { HttpResponse::TooManyRequests().finish() }),
+            )
+            .route(
+                "/failing",
+                web::get().to(|| async { HttpResponse::InternalServerError().finish() }),
+            ),
+    )
+    .await;
+
+    for path in ["/limited", "/failing"] {
+        let req = test::TestRequest::get().uri(path).to_request();
+        test::call_and_read_body(&app, req).await;
+    }
+
+    let spans = collector.spans();
+    assert_eq!(spans[0].status_code, SpanStatus::Error);
+    assert_eq!(spans[0].status_message, "rate limited");
+    assert_eq!(spans[1].status_code, SpanStatus::Error);
+    assert_eq!(spans[1].status_message, "Internal Server Error");
+}
+
+#[actix_web::test]
+async fn injects_trace_response_into_responses_and_errors() {
+    let collector = SpanCollector::default();
+    let _provider = install(&collector);
+    let service = fn_service(|req: ServiceRequest| async move {
+        match req.path() {
+            "/failing" => Err(ErrorInternalServerError("database unavailable")),
+            _ => Ok(req.into_response(HttpResponse::Ok().finish())),
+        }
+    });
+    let logger = TracingLogger::default().with_trace_response(TraceResponse::new());
+    let middleware = logger.new_transform(service).await.unwrap();
+
+    let mut headers = Vec::new();
+    for path in ["/", "/failing"] {
+        let req = test::TestRequest::get().uri(path).to_srv_request();
+        let res = match middleware.call(req).await {
+            Ok(res) => res.into_parts().1.map_into_boxed_body(),
+            Err(err) => err.error_response(),
+        };
+        headers.push((res.status(), res.headers().get("traceresponse").cloned()));
+    }
+
+    let spans = collector.spans();
+    let traceparent = |span: &SpanData| {
+        format!(
+            "00-{:032x}-{:016x}-01",
+            span.span_context.trace_id(),
+            span.span_context.span_id()
+        )
+    };
+    assert_eq!(headers[0].0, StatusCode::OK);
+    assert_eq!(
+        headers[0].1.as_ref().unwrap(),
+        traceparent(&spans[0]).as_str()
+    );
+    assert_eq!(headers[1].0, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        headers[1].1.as_ref().unwrap(),
+        traceparent(&spans[1]).as_str()
+    );
+}
+
+#[actix_web::test]
+async fn trusts_forwarded_client_ip_of_trusted_peers_only() {
+    let collector = SpanCollector::default();
+    let _provider = install(&collector);
+    let logger = TracingLogger::default()
+        .with_trusted_proxies(TrustedProxies::new().trust_private_networks());
+    let middleware = logger.new_transform(test::ok_service()).await.unwrap();
+
+    let requests = [
+        (Some("10.0.0.1:4711"), "203.0.113.1"),
+        (None, "203.0.113.1"),
+        (Some("10.0.0.1:4711"), "unknown"),
+    ];
+    for (peer, forwarded_for) in requests {
+        let mut req = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::X_FORWARDED_FOR, forwarded_for));
+        if let Some(peer) = peer {
+            req = req.peer_addr(peer.parse().unwrap());
+        }
+        middleware.call(req.to_srv_request()).await.unwrap();
+    }
+
+    let spans = collector.spans();
+    let client_ip = |span: &SpanData| {
+        span.attributes
+            .get(&Key::new("http.client_ip"))
+            .map(|ip| ip.as_str().into_owned())
+    };
+    assert_eq!(client_ip(&spans[0]).as_deref(), Some("203.0.113.1"));
+    // the forwarding headers are not trusted without a peer
+    assert_eq!(client_ip(&spans[1]).as_deref(), Some(""));
+    // nor the proxies behind an unknown hop
+    assert_eq!(client_ip(&spans[2]).as_deref(), Some(""));
+}
+
+// Records every request as an error, whatever the classifier.
+struct FailingRootSpanBuilder;
+
+impl RootSpanBuilder for FailingRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> tracing::Span {
+        root_span!(request)
+    }
+
+    fn on_request_end<B>(span: tracing::Span, _outcome: &Result<ServiceResponse<B>, Error>) {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+#[actix_web::test]
+async fn keeps_status_of_custom_root_span_builder() {
+    let collector = SpanCollector::default();
+    let _provider = install(&collector);
+    let logger = TracingLogger::<FailingRootSpanBuilder>::new()
+        .with_status_classifier(DefaultStatusClassifier);
+    let middleware = logger.new_transform(test::ok_service()).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/").to_srv_request();
+    middleware.call(req).await.unwrap();
+
+    let spans = collector.spans();
+    assert_eq!(spans[0].status_code, SpanStatus::Error);
+}
+
+#[actix_web::test]
+async fn records_errors_of_failed_requests_by_default() {
+    let collector = SpanCollector::default();
+    let _provider = install(&collector);
+    let service = fn_service(|req: ServiceRequest| async move {
+        match req.path() {
+            "/invalid" => Err(ErrorBadRequest("invalid id")),
+            "/failing" => Err(ErrorInternalServerError("database unavailable")),
+            _ => Ok(req.into_response(HttpResponse::ServiceUnavailable().finish())),
+        }
+    });
+    let middleware = TracingLogger::default()
+        .new_transform(service)
+        .await
+        .unwrap();
+
+    for path in ["/invalid", "/failing", "/unavailable"] {
+        let req = test::TestRequest::get().uri(path).to_srv_request();
+        let _ = middleware.call(req).await;
+    }
+
+    let spans = collector.spans();
+    let statuses: Vec<_> = spans.iter().map(|span| span.status_code).collect();
+    // server errors without an error are not errors
+    assert_eq!(
+        statuses,
+        [SpanStatus::Ok, SpanStatus::Error, SpanStatus::Ok]
+    );
+}